@@ -0,0 +1,42 @@
+use dexcreeper::graph::dynamic_graph::DynamicGraph;
+use dexcreeper::graph::export::GraphExport;
+use dexcreeper::graph::{schedule_update, topology_file};
+use dexcreeper::jupiter::quote_jupiter;
+use dexcreeper::search::cached_search;
+
+// Quote every edge once and print the graph as Graphviz DOT, or as JSON with --json
+// Usage: exe_export [--json] [topology.json]
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let json = args.iter().any(|arg| arg == "--json");
+    // a mistyped flag would otherwise be read as the topology path
+    if let Some(arg) = args.iter().find(|arg| arg.starts_with("--") && *arg != "--json") {
+        eprintln!("Unknown option: {}", arg);
+        return;
+    }
+    let graph: DynamicGraph = match args.iter().find(|arg| *arg != "--json") {
+        Some(path) => match topology_file::load_topology(path) {
+            Ok(topology) => topology.into_dynamic_graph(),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                return;
+            }
+        },
+        None => schedule_update::create_graph(),
+    };
+
+    graph.update_edge_attr(0, 16, quote_jupiter).await;
+    let result = cached_search::search_cached(&graph, 4, std::time::Duration::from_secs(10));
+    let profitable: Vec<_> = result.opportunities.into_iter().filter(|o| o.is_profitable()).collect();
+
+    let export = GraphExport::from_dynamic(&graph, &profitable);
+    if json {
+        match export.to_json() {
+            Ok(content) => println!("{}", content),
+            Err(e) => eprintln!("Can't serialize the graph: {}", e),
+        }
+    } else {
+        print!("{}", export.to_dot());
+    }
+}
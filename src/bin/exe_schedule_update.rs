@@ -1,6 +1,133 @@
-use dexcreeper::graph::schedule_update;
+use std::path::PathBuf;
+use std::time::Duration;
+use dexcreeper::graph::discovery::{self, DiscoveryConfig, MintDiscovery};
+use dexcreeper::graph::schedule_update::{PersistOptions, UpdateOptions};
+use dexcreeper::graph::mutation::TopologyChange;
+use dexcreeper::graph::{persist, schedule_update, topology_file};
+use tokio::io::AsyncBufReadExt;
+use tokio::sync::mpsc;
 
+// Usage: exe_schedule_update [--discover] [--state state.json] [--max-age secs] [--ladder 0.1,10] [--negative-cycles] [--changes-stdin] [topology.json]
+// With --discover, the mints seen in 5 quotes are added, paired with the 2 most connected tokens, up to 30 tokens in all
+// With --state, the graph is saved every 30 seconds and on Ctrl-C, and restored from the file when it exists,
+// keeping the quotes younger than --max-age seconds, 600 by default
+// With --ladder, each edge is also quoted at these multiples of its quote amount, to estimate the price impact
+// With --negative-cycles, the cycles are found with Bellman-Ford instead of the cycle index, for large graphs
+// With --changes-stdin, each line of stdin is a JSON array of topology changes (see mutation.rs),
+// applied all or none as they come, during an update round too
 #[tokio::main]
 async fn main() {
-    schedule_update::schedule_update().await;
+    let mut discover = false;
+    let mut negative_cycles = false;
+    let mut changes_stdin = false;
+    let mut state_path: Option<PathBuf> = None;
+    let mut max_age = Duration::from_secs(600);
+    let mut ladder_factors: Vec<f64> = vec![];
+    let mut topology_path: Option<String> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--discover" => discover = true,
+            "--negative-cycles" => negative_cycles = true,
+            "--changes-stdin" => changes_stdin = true,
+            "--state" => match args.next() {
+                Some(path) => state_path = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("--state needs a file path");
+                    return;
+                }
+            },
+            "--max-age" => {
+                let secs = args.next().unwrap_or_default();
+                match secs.parse::<u64>() {
+                    Ok(secs) => max_age = Duration::from_secs(secs),
+                    Err(_) => {
+                        eprintln!("Invalid max age: {}", secs);
+                        return;
+                    }
+                }
+            }
+            "--ladder" => {
+                let factors = args.next().unwrap_or_default();
+                match factors.split(',').map(|factor| factor.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>() {
+                    Ok(factors) if factors.iter().all(|factor| *factor > 0.0) => ladder_factors = factors,
+                    _ => {
+                        eprintln!("Invalid ladder factors: {}", factors);
+                        return;
+                    }
+                }
+            }
+            // a mistyped flag would otherwise be read as the topology path
+            _ if arg.starts_with("--") => {
+                eprintln!("Unknown option: {}", arg);
+                return;
+            }
+            _ if topology_path.is_none() => topology_path = Some(arg),
+            _ => {
+                eprintln!("Unexpected argument: {}", arg);
+                return;
+            }
+        }
+    }
+
+    let mut graph = match (&state_path, &topology_path) {
+        (Some(path), _) if path.exists() => match persist::load_snapshot(path, max_age) {
+            Ok(graph) => graph,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                return;
+            }
+        },
+        // The topology file is optional, the default tokens are used without it
+        (_, Some(path)) => match topology_file::load_topology(path) {
+            Ok(topology) => topology.into_dynamic_graph(),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                return;
+            }
+        },
+        (_, None) => schedule_update::create_graph(),
+    };
+
+    graph.ladder_factors = ladder_factors;
+
+    let mut options = UpdateOptions {
+        negative_cycles,
+        ..Default::default()
+    };
+    if discover {
+        options.discovery = Some(MintDiscovery::new(DiscoveryConfig {
+            min_count: 5,
+            max_new_tokens: 2,
+            max_total_tokens: 30,
+            hubs: discovery::hubs_by_degree(&graph.topology(), 2),
+            auto_add: true,
+        }));
+    }
+    options.persist = state_path.map(|path| PersistOptions {
+        path,
+        interval: Duration::from_secs(30),
+    });
+    if changes_stdin {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(read_changes(sender));
+        options.changes = Some(receiver);
+    }
+    schedule_update::schedule_update_graph_with(graph, options).await;
+}
+
+// Send each line of stdin to the updater, until stdin is closed or the updater stops
+async fn read_changes(sender: mpsc::UnboundedSender<Vec<TopologyChange>>) {
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Vec<TopologyChange>>(&line) {
+            Ok(changes) => if sender.send(changes).is_err() {
+                return;
+            },
+            Err(e) => eprintln!("Invalid topology change {}: {}", line, e),
+        }
+    }
 }
@@ -1,10 +1,50 @@
-use dexcreeper::search::search;
+use dexcreeper::graph::topology_file;
+use dexcreeper::jupiter::quote_jupiter;
+use dexcreeper::search::{ranking, search, trade_size};
 
 #[tokio::main]
 async fn main() {
+    // The topology file is optional, the default tokens are used without it
+    let (graph, start_node, start_amount) = match std::env::args().nth(1) {
+        Some(path) => match topology_file::load_topology(&path) {
+            Ok(topology) => (topology.graph, topology.start_node, topology.start_amount),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                return;
+            }
+        },
+        None => (search::create_static_graph(), 1, 1000000000),
+    };
+
+    let report = graph.validate(start_node);
+    if !report.is_ok() {
+        eprintln!("{}", report);
+    }
+
     let start = std::time::Instant::now();
-    let graph = search::create_static_graph();
-    let results = search::search(graph, 1, 1000000000, 4).await;
+    // An opportunity found after 3 seconds is stale, keep what was found by then
+    let limits = search::SearchLimits::none().with_deadline(start + std::time::Duration::from_secs(3));
+    let Some(outcome) = search::search_until(&graph, start_node, start_amount, 4, 16, limits, quote_jupiter).await else {
+        return;
+    };
     let end = start.elapsed();
-    println!("{:?}", end);
-}
\ No newline at end of file
+    println!("{:?}, {:?} after {} levels", end, outcome.completion, outcome.levels_completed);
+    let opportunities = outcome.opportunities;
+
+    // Only the few best cycles are worth acting on, fees are the 5000 lamports signature fee plus a rough per-hop compute cost
+    let objective = ranking::Objective::NetProfit { fee_per_tx: 5000, fee_per_hop: 10000 };
+    let opportunities = ranking::top_k(opportunities, objective, 5, 1);
+    for opportunity in &opportunities {
+        println!("path: {}, end amount: {}, net profit: {}, roi: {:.2} bps",
+                 opportunity.symbols(&graph).join(" -> "), opportunity.end_amount, objective.net_profit(opportunity), opportunity.roi_bps);
+    }
+
+    // Find the best trade size of each profitable cycle
+    for opportunity in &opportunities {
+        let sized = trade_size::optimize_trade_size(&graph, &opportunity.edge_ids(), start_amount / 10, start_amount.saturating_mul(10), 12, quote_jupiter).await;
+        if let Some(best) = sized.best {
+            println!("path: {}, best amount: {}, profit: {}, samples: {:?}",
+                     best.symbols(&graph).join(" -> "), best.start_amount, best.gross_profit, sized.samples);
+        }
+    }
+}
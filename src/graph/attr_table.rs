@@ -0,0 +1,233 @@
+// The state of a DynamicGraph as immutable, epoch-numbered tables: the topology, the pruning and the edge attributes
+// A reader loads the current table once and sees every edge as it was at one instant, with the topology its edge ids
+// refer to, so two legs of a cycle can't come from before and after an update, nor from two topologies. A writer never
+// modifies a table: it copies the current one with the new attributes (the attributes and the topology themselves are
+// shared, only the Arcs are copied) and swaps the pointer atomically. Loading and swapping are lock-free, so readers
+// never block the updater, and there is no lock to poison. A copy costs O(n_edge), so the updater publishes its quotes
+// in batches rather than one table per quote.
+// The store is behind an Arc in the DynamicGraph, a clone of the Arc is a handle another task can read from at any time.
+
+use std::sync::Arc;
+use arc_swap::ArcSwap;
+use crate::graph::dynamic_graph::EdgeAttribute;
+use crate::graph::static_graph::StaticGraph;
+
+pub struct AttrTable {
+    pub epoch: u64, // incremented by every write, two loads with the same epoch saw the same table
+    pub version: u64, // incremented each time the topology is replaced, see mutation.rs
+    pub topology: Arc<StaticGraph>,
+    pub start_node: usize,
+    pub start_amount: u64,
+    pub live_edges: Option<Arc<Vec<bool>>>, // live_edges[i] = false if edge i is pruned and never updated, None if nothing is pruned
+    pub attrs: Vec<Arc<EdgeAttribute>>, // attrs[i] is the attribute of edge i of the topology
+}
+
+impl AttrTable {
+    pub fn get(&self, edge_idx: usize) -> &EdgeAttribute {
+        &self.attrs[edge_idx]
+    }
+
+    pub fn len(&self) -> usize {
+        self.attrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attrs.is_empty()
+    }
+
+    pub fn is_live(&self, edge_idx: usize) -> bool {
+        self.live_edges.as_ref().is_none_or(|live| live[edge_idx])
+    }
+
+    // A copy of the table with the next epoch, and the same Arcs
+    fn next(&self) -> Self {
+        Self {
+            epoch: self.epoch + 1,
+            version: self.version,
+            topology: self.topology.clone(),
+            start_node: self.start_node,
+            start_amount: self.start_amount,
+            live_edges: self.live_edges.clone(),
+            attrs: self.attrs.clone(),
+        }
+    }
+}
+
+pub struct AttrStore {
+    current: ArcSwap<AttrTable>,
+}
+
+impl AttrStore {
+    // Topology version 0, with a fresh attribute for every edge
+    pub fn new(topology: Arc<StaticGraph>, start_node: usize, start_amount: u64) -> Self {
+        let attrs = (0..topology.n_edge()).map(|_| Arc::new(EdgeAttribute::new())).collect();
+        Self {
+            current: ArcSwap::from_pointee(AttrTable {
+                epoch: 0,
+                version: 0,
+                topology,
+                start_node,
+                start_amount,
+                live_edges: None,
+                attrs,
+            }),
+        }
+    }
+
+    // The current table, it stays valid and unchanged however long it is kept
+    pub fn load(&self) -> Arc<AttrTable> {
+        self.current.load_full()
+    }
+
+    // Publish a new table where each (edge_idx, attr) of the batch is set, returns its epoch
+    // The edge ids refer to `topology`, the topology the quotes were requested on. If it was replaced since, each
+    // edge is found again in the current topology by its mints and DEX filter, and the edges removed since are
+    // dropped. Concurrent writers retry on a newer table, so no write is lost
+    pub fn set_many(&self, topology: &Arc<StaticGraph>, updates: Vec<(usize, EdgeAttribute)>) -> u64 {
+        let updates: Vec<(usize, Arc<EdgeAttribute>)> = updates.into_iter()
+            .map(|(edge_idx, attr)| (edge_idx, Arc::new(attr)))
+            .collect();
+        let previous = self.current.rcu(|table| {
+            let mut next = table.next();
+            for (edge_idx, attr) in &updates {
+                let edge_idx = if Arc::ptr_eq(topology, &table.topology) {
+                    Some(*edge_idx)
+                } else {
+                    table.topology.find_edge(topology.input_mint(*edge_idx), topology.output_mint(*edge_idx),
+                                             &topology.edge_info[*edge_idx].dex_filter)
+                };
+                if let Some(edge_idx) = edge_idx {
+                    next.attrs[edge_idx] = attr.clone();
+                }
+            }
+            next
+        });
+        previous.epoch + 1
+    }
+
+    // Publish the attributes of every edge of the current topology at once, e.g. restored from a file
+    pub fn set_all(&self, attrs: Vec<Arc<EdgeAttribute>>) -> u64 {
+        let previous = self.current.rcu(|table| {
+            assert_eq!(attrs.len(), table.topology.n_edge(), "One attribute per edge of the topology");
+            AttrTable {
+                attrs: attrs.clone(),
+                ..table.next()
+            }
+        });
+        previous.epoch + 1
+    }
+
+    // Swap in a new topology as the next version, returns the version
+    // old_ids[i] is the edge of the current topology that edge i of the new one replaces, it keeps its attribute
+    // (None for a new edge, which gets a fresh one). The pruning belongs to the previous topology and is dropped.
+    // Only DynamicGraph::apply_changes calls it, with &mut self, so the topology can't change under old_ids
+    pub fn set_topology(&self, topology: Arc<StaticGraph>, start_node: usize, old_ids: &[Option<usize>]) -> u64 {
+        let previous = self.current.rcu(|table| AttrTable {
+            version: table.version + 1,
+            topology: topology.clone(),
+            start_node,
+            live_edges: None,
+            attrs: old_ids.iter()
+                .map(|old_id| old_id.map_or_else(|| Arc::new(EdgeAttribute::new()), |old_id| table.attrs[old_id].clone()))
+                .collect(),
+            ..table.next()
+        });
+        previous.version + 1
+    }
+
+    // Prune the edges of topology `version`, nothing is changed if the topology was replaced since
+    pub fn set_live_edges(&self, version: u64, live_edges: Option<Vec<bool>>) {
+        let live_edges = live_edges.map(Arc::new);
+        self.current.rcu(|table| {
+            if table.version != version {
+                return Arc::clone(table);
+            }
+            Arc::new(AttrTable {
+                live_edges: live_edges.clone(),
+                ..table.next()
+            })
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use tokio::sync::Semaphore;
+    use super::*;
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::dynamic_graph::DynamicGraph;
+    use crate::jupiter::quote::QuoteResponse;
+    use crate::search::cached_search::QuoteSnapshot;
+
+    // S <-> A <-> B <-> S, quoted at start_amount out of S and by value from the decimals for the others
+    fn graph() -> DynamicGraph {
+        let static_graph = GraphBuilder::new()
+            .test_nodes(&[("S", Some(6)), ("A", Some(6)), ("B", Some(6))])
+            .mint_pair("S", "A")
+            .mint_pair("A", "B")
+            .mint_pair("B", "S")
+            .build()
+            .unwrap();
+        let start_node = static_graph.node_id("S").unwrap();
+        DynamicGraph::new(Arc::new(static_graph), start_node, 1000)
+    }
+
+    #[tokio::test]
+    async fn a_handle_reads_the_quotes_of_a_round_while_it_runs() {
+        let graph = graph();
+        let handle = graph.attrs.clone();
+        // each quote waits for a permit, so the test decides when the round goes on
+        let gate = Arc::new(Semaphore::new(0));
+        let update_fn = {
+            let gate = gate.clone();
+            move |params| {
+                let gate = gate.clone();
+                async move {
+                    gate.acquire().await.unwrap().forget();
+                    Ok(QuoteResponse::for_params(&params, 990))
+                }
+            }
+        };
+        // one quote at a time, published one by one
+        let round = tokio::spawn(graph.update_edge_attr(0, 1, update_fn));
+
+        let before = QuoteSnapshot::load(&handle);
+        assert_eq!(before.n_edge(), 6);
+        assert!((0..before.n_edge()).all(|edge_idx| before.quote(edge_idx).is_none()));
+
+        gate.add_permits(1);
+        while handle.load().epoch == before.attrs.epoch {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        let during = QuoteSnapshot::load(&handle);
+        assert_eq!((0..during.n_edge()).filter(|&edge_idx| during.quote(edge_idx).is_some()).count(), 1);
+        // a snapshot is never changed by a later write
+        assert!((0..before.n_edge()).all(|edge_idx| before.quote(edge_idx).is_none()));
+
+        gate.add_permits(5);
+        let results = round.await.unwrap();
+        assert_eq!(results.len(), 6);
+        let after = QuoteSnapshot::load(&handle);
+        for edge_idx in 0..after.n_edge() {
+            let (quote, _) = after.quote(edge_idx).unwrap();
+            assert_eq!(quote.input_mint, after.topology.input_mint(edge_idx));
+            assert_eq!(quote.output_mint, after.topology.output_mint(edge_idx));
+        }
+    }
+
+    #[test]
+    fn the_pruning_of_a_replaced_topology_is_ignored() {
+        let graph = graph();
+        let store = &graph.attrs;
+        let table = store.load();
+        let old_ids: Vec<Option<usize>> = (0..table.len()).map(Some).collect();
+        store.set_topology(table.topology.clone(), table.start_node, &old_ids);
+        store.set_live_edges(table.version, Some(vec![false; table.len()]));
+        let current = store.load();
+        assert_eq!(current.version, table.version + 1);
+        assert!(current.live_edges.is_none());
+        // the kept edges keep their attributes
+        assert!((0..current.len()).all(|edge_idx| Arc::ptr_eq(&current.attrs[edge_idx], &table.attrs[edge_idx])));
+    }
+}
@@ -0,0 +1,208 @@
+// Build a StaticGraph from a list of tokens and a list of pairs
+// Node ids are assigned from 1 in the order the tokens are added, and edges are added in the order of the pairs,
+// a bidirectional pair adding the a -> b edge first and then the b -> a edge
+// The same pair can be added several times with different DEX filters, each becoming its own edge
+
+use crate::graph::static_graph::{DexFilter, GraphError, NodeInfo, StaticGraph};
+use crate::mints::mints::TokenInfo;
+
+pub struct GraphBuilder {
+    nodes: Vec<NodeInfo>, // nodes[i] is the token of node i + 1
+    edges: Vec<(String, String, DexFilter)>, // (input mint, output mint, DEX filter) of each edge
+    conflict: Option<String>, // the first mint added again with a different symbol or decimals
+}
+
+impl Default for GraphBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GraphBuilder {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![],
+            edges: vec![],
+            conflict: None,
+        }
+    }
+
+    // Add a token as a node, a token that is already in the graph with the same metadata is ignored
+    // and one with a different symbol or decimals fails the build
+    pub fn token(self, token: &TokenInfo) -> Self {
+        self.node(NodeInfo::from(token))
+    }
+
+    pub fn node(mut self, node: NodeInfo) -> Self {
+        self.add_node(node);
+        self
+    }
+
+    pub fn tokens(self, tokens: &[&TokenInfo]) -> Self {
+        tokens.iter().fold(self, |builder, token| builder.token(token))
+    }
+
+    // Add the edges a -> b and b -> a, tokens that are not in the graph yet are added as nodes
+    pub fn pair(mut self, a: &TokenInfo, b: &TokenInfo) -> Self {
+        self.add_node(NodeInfo::from(a));
+        self.add_node(NodeInfo::from(b));
+        self.mint_pair(a.mint, b.mint)
+    }
+
+    // Same as pair, but both mints must have been added as nodes before build
+    pub fn mint_pair(self, a: &str, b: &str) -> Self {
+        self.mint_pair_via(a, b, DexFilter::default())
+    }
+
+    // Add the edges a -> b and b -> a, both only quoted on the DEXes allowed by dex_filter
+    pub fn pair_via(mut self, a: &TokenInfo, b: &TokenInfo, dex_filter: DexFilter) -> Self {
+        self.add_node(NodeInfo::from(a));
+        self.add_node(NodeInfo::from(b));
+        self.mint_pair_via(a.mint, b.mint, dex_filter)
+    }
+
+    pub fn mint_pair_via(mut self, a: &str, b: &str, dex_filter: DexFilter) -> Self {
+        self.edges.push((a.to_string(), b.to_string(), dex_filter.clone()));
+        self.edges.push((b.to_string(), a.to_string(), dex_filter));
+        self
+    }
+
+    pub fn pairs(self, pairs: &[(&TokenInfo, &TokenInfo)]) -> Self {
+        pairs.iter().fold(self, |builder, (a, b)| builder.pair(a, b))
+    }
+
+    // Add only the edge from -> to
+    pub fn one_way(mut self, from: &TokenInfo, to: &TokenInfo) -> Self {
+        self.add_node(NodeInfo::from(from));
+        self.add_node(NodeInfo::from(to));
+        self.mint_one_way(from.mint, to.mint)
+    }
+
+    pub fn mint_one_way(self, from: &str, to: &str) -> Self {
+        self.mint_one_way_via(from, to, DexFilter::default())
+    }
+
+    pub fn one_way_via(mut self, from: &TokenInfo, to: &TokenInfo, dex_filter: DexFilter) -> Self {
+        self.add_node(NodeInfo::from(from));
+        self.add_node(NodeInfo::from(to));
+        self.mint_one_way_via(from.mint, to.mint, dex_filter)
+    }
+
+    pub fn mint_one_way_via(mut self, from: &str, to: &str, dex_filter: DexFilter) -> Self {
+        self.edges.push((from.to_string(), to.to_string(), dex_filter));
+        self
+    }
+
+    // Fails on a mint added with conflicting metadata, then on the first edge whose mint isn't a node,
+    // that loops on a node, or that was already added
+    pub fn build(self) -> Result<StaticGraph, GraphError> {
+        if let Some(mint) = self.conflict {
+            return Err(GraphError::DuplicateMint(mint));
+        }
+        let mut graph = StaticGraph::new(self.nodes);
+        for (input_mint, output_mint, dex_filter) in self.edges {
+            let from = graph.node_id(&input_mint).ok_or(GraphError::UnknownMint(input_mint))?;
+            let to = graph.node_id(&output_mint).ok_or(GraphError::UnknownMint(output_mint))?;
+            graph.add_edge_via(from, to, dex_filter)?;
+        }
+        Ok(graph)
+    }
+
+    fn add_node(&mut self, node: NodeInfo) {
+        match self.nodes.iter().find(|n| n.mint == node.mint) {
+            None => self.nodes.push(node),
+            Some(existing) if *existing != node && self.conflict.is_none() => self.conflict = Some(node.mint),
+            Some(_) => {},
+        }
+    }
+
+    // Tokens whose mint is their symbol, (symbol, decimals) each, for the graphs of the unit tests
+    #[cfg(test)]
+    pub(crate) fn test_nodes(self, tokens: &[(&str, Option<u8>)]) -> Self {
+        tokens.iter().fold(self, |builder, &(symbol, decimals)| builder.node(NodeInfo {
+            mint: symbol.to_string(),
+            symbol: symbol.to_string(),
+            decimals,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mints::mints::{self, USDC, USDT, WSOL};
+
+    #[test]
+    fn numbers_the_nodes_and_edges_in_the_order_they_are_added() {
+        let graph = GraphBuilder::new()
+            .pair(&USDC, &WSOL)
+            .one_way(&WSOL, &USDT)
+            .build()
+            .unwrap();
+        assert_eq!(graph.n_node(), 3);
+        assert_eq!([USDC.mint, WSOL.mint, USDT.mint].map(|mint| graph.node_id(mint)), [Some(1), Some(2), Some(3)]);
+        assert_eq!(graph.node(2).symbol, "WSOL");
+        assert_eq!(graph.node(2).decimals, Some(9));
+        // a pair adds a -> b first, then b -> a
+        let endpoints: Vec<(&str, &str)> = (0..graph.n_edge()).map(|e| (graph.input_mint(e), graph.output_mint(e))).collect();
+        assert_eq!(endpoints, [(USDC.mint, WSOL.mint), (WSOL.mint, USDC.mint), (WSOL.mint, USDT.mint)]);
+    }
+
+    #[test]
+    fn the_same_pair_on_other_dexes_adds_parallel_edges() {
+        let graph = GraphBuilder::new()
+            .pair(&WSOL, &USDC)
+            .pair_via(&WSOL, &USDC, DexFilter::only(&["Raydium"]))
+            .one_way_via(&USDC, &WSOL, DexFilter::excluding(&["Raydium"]))
+            .build()
+            .unwrap();
+        assert_eq!(graph.n_edge(), 5);
+        assert_eq!(graph.out_edges(graph.node_id(USDC.mint).unwrap()).count(), 3);
+        assert!(graph.find_edge(USDC.mint, WSOL.mint, &DexFilter::only(&["Raydium"])).is_some());
+    }
+
+    #[test]
+    fn an_edge_added_twice_fails_the_build() {
+        let result = GraphBuilder::new().pair(&WSOL, &USDC).one_way(&WSOL, &USDC).build();
+        assert!(matches!(result.err(), Some(GraphError::DuplicateEdge { from: 1, to: 2, .. })));
+    }
+
+    #[test]
+    fn a_mint_pair_needs_both_nodes() {
+        let result = GraphBuilder::new().token(&WSOL).mint_pair(WSOL.mint, USDC.mint).build();
+        assert_eq!(result.err(), Some(GraphError::UnknownMint(USDC.mint.to_string())));
+        let result = GraphBuilder::new().tokens(&[&WSOL, &USDC]).mint_pair(WSOL.mint, USDC.mint).build();
+        assert_eq!(result.unwrap().n_edge(), 2);
+    }
+
+    #[test]
+    fn the_default_graph_starts_at_wsol() {
+        let graph = GraphBuilder::new().tokens(&mints::DEFAULT_TOKENS).pairs(&mints::DEFAULT_PAIRS).build().unwrap();
+        assert_eq!(graph.node_id(WSOL.mint), Some(1));
+        assert_eq!(graph.n_node(), mints::DEFAULT_TOKENS.len());
+        assert_eq!(graph.n_edge(), 2 * mints::DEFAULT_PAIRS.len());
+    }
+
+    #[test]
+    fn a_token_added_again_with_the_same_metadata_is_ignored() {
+        let graph = GraphBuilder::new()
+            .test_nodes(&[("A", Some(6)), ("B", Some(9)), ("A", Some(6))])
+            .mint_pair("A", "B")
+            .build()
+            .unwrap();
+        assert_eq!(graph.n_node(), 2);
+    }
+
+    #[test]
+    fn a_token_added_again_with_other_metadata_fails_the_build() {
+        let result = GraphBuilder::new()
+            .test_nodes(&[("A", Some(6)), ("B", Some(9)), ("A", Some(9))])
+            .mint_pair("A", "B")
+            .build();
+        assert_eq!(result.err(), Some(GraphError::DuplicateMint("A".to_string())));
+
+        let renamed = NodeInfo { mint: "A".to_string(), symbol: "A2".to_string(), decimals: Some(6) };
+        let result = GraphBuilder::new().test_nodes(&[("A", Some(6))]).node(renamed).build();
+        assert_eq!(result.err(), Some(GraphError::DuplicateMint("A".to_string())));
+    }
+}
@@ -0,0 +1,125 @@
+// A compressed sparse row (CSR) copy of a StaticGraph
+// The chain forward star is convenient for add_edge, but walking it chases next[] through memory.
+// Once the graph is built, the edges of each node can be laid out contiguously instead:
+// the out edges of node i are edge_ids[offsets[i]..offsets[i + 1]], with their destinations in targets[..].
+// Edge ids are the ids of the StaticGraph, so they still index edge_info and DynamicGraph::attr.
+// Within a node, edges keep the order of the chain forward star (most recently added first),
+// so a search gives the same results on both layouts.
+
+use crate::graph::static_graph::StaticGraph;
+
+#[derive(Debug, Clone)]
+pub struct CsrGraph {
+    pub offsets: Vec<usize>, // offsets[i]..offsets[i + 1] is the range of the out edges of node i, len = n_node + 2
+    pub edge_ids: Vec<usize>, // the edge ids, grouped by source node
+    pub targets: Vec<usize>, // targets[k] is the destination node of edge_ids[k]
+    pub rev_offsets: Vec<usize>, // rev_offsets[i]..rev_offsets[i + 1] is the range of the in edges of node i
+    pub rev_edge_ids: Vec<usize>, // the edge ids, grouped by destination node
+    pub sources: Vec<usize>, // sources[k] is the source node of rev_edge_ids[k]
+}
+
+impl CsrGraph {
+    pub fn from_static(graph: &StaticGraph) -> Self {
+        let n_slot = graph.head.len();
+        let mut offsets = Vec::with_capacity(n_slot + 1);
+        let mut edge_ids = Vec::with_capacity(graph.n_edge());
+        let mut targets = Vec::with_capacity(graph.n_edge());
+        let mut rev_offsets = Vec::with_capacity(n_slot + 1);
+        let mut rev_edge_ids = Vec::with_capacity(graph.n_edge());
+        let mut sources = Vec::with_capacity(graph.n_edge());
+        for node in 0..n_slot {
+            offsets.push(edge_ids.len());
+            for edge_idx in graph.out_edges(node) {
+                edge_ids.push(edge_idx);
+                targets.push(graph.to[edge_idx]);
+            }
+            rev_offsets.push(rev_edge_ids.len());
+            for edge_idx in graph.in_edges(node) {
+                rev_edge_ids.push(edge_idx);
+                sources.push(graph.edge(edge_idx).0);
+            }
+        }
+        offsets.push(edge_ids.len());
+        rev_offsets.push(rev_edge_ids.len());
+        Self {
+            offsets,
+            edge_ids,
+            targets,
+            rev_offsets,
+            rev_edge_ids,
+            sources,
+        }
+    }
+
+    pub fn n_node(&self) -> usize {
+        self.offsets.len() - 2
+    }
+
+    // The ids of the out edges of `node`
+    pub fn out_edge_ids(&self, node: usize) -> &[usize] {
+        &self.edge_ids[self.offsets[node]..self.offsets[node + 1]]
+    }
+
+    // The destinations of the out edges of `node`, in the same order as out_edge_ids
+    pub fn out_targets(&self, node: usize) -> &[usize] {
+        &self.targets[self.offsets[node]..self.offsets[node + 1]]
+    }
+
+    // (edge id, destination) of the out edges of `node`
+    pub fn out_edges(&self, node: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.out_edge_ids(node).iter().copied().zip(self.out_targets(node).iter().copied())
+    }
+
+    // The ids of the in edges of `node`
+    pub fn in_edge_ids(&self, node: usize) -> &[usize] {
+        &self.rev_edge_ids[self.rev_offsets[node]..self.rev_offsets[node + 1]]
+    }
+
+    // (edge id, source) of the in edges of `node`
+    pub fn in_edges(&self, node: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let range = self.rev_offsets[node]..self.rev_offsets[node + 1];
+        self.rev_edge_ids[range.clone()].iter().copied().zip(self.sources[range].iter().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::static_graph::{DexFilter, StaticGraph};
+
+    fn graph() -> StaticGraph {
+        GraphBuilder::new()
+            .test_nodes(&[("A", Some(6)), ("B", Some(6)), ("C", Some(6)), ("D", Some(6))])
+            .mint_pair("A", "B")
+            .mint_pair("B", "C")
+            .mint_one_way("A", "C")
+            .mint_one_way_via("A", "B", DexFilter::only(&["Raydium"]))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn keeps_the_order_of_the_chain_forward_star() {
+        let graph = graph();
+        let csr = graph.freeze();
+        assert_eq!(csr.n_node(), 4);
+        for node in 0..=graph.n_node() {
+            assert_eq!(csr.out_edge_ids(node), graph.out_edges(node).collect::<Vec<_>>());
+            assert_eq!(csr.in_edge_ids(node), graph.in_edges(node).collect::<Vec<_>>());
+            assert!(csr.out_edges(node).all(|(edge_idx, to)| graph.to[edge_idx] == to));
+            assert!(csr.in_edges(node).all(|(edge_idx, from)| graph.edge_info[edge_idx].input_node == from));
+        }
+        // D has no edge, the empty node neither
+        assert!(csr.out_edge_ids(4).is_empty() && csr.in_edge_ids(4).is_empty());
+        assert!(csr.out_edge_ids(0).is_empty());
+    }
+
+    #[test]
+    fn add_edge_drops_the_cached_layout() {
+        let mut graph = graph();
+        assert_eq!(graph.frozen().out_edge_ids(3).len(), 1);
+        let c_d = graph.add_edge(3, 4).unwrap();
+        assert_eq!(graph.frozen().out_edge_ids(3), [c_d, graph.find_edge("C", "B", &DexFilter::default()).unwrap()]);
+        assert_eq!(graph.frozen().in_edge_ids(4), [c_d]);
+    }
+}
@@ -0,0 +1,207 @@
+// A precomputed index of the simple cycles of a StaticGraph
+// The topology doesn't change between searches, so the cycles are enumerated once and the searches
+// only have to evaluate them. Cycles are enumerated the way Johnson's algorithm orders them: the cycles
+// rooted at node s only visit nodes greater than s, so every cycle is found exactly once, starting from
+// its smallest node. That rotation is the canonical form of the cycle.
+
+use crate::graph::static_graph::StaticGraph;
+
+#[derive(Debug, Clone)]
+pub struct IndexedCycle {
+    pub nodes: Vec<usize>, // nodes[i] is the source node of edges[i]
+    pub edges: Vec<usize>, // the edge ids of the cycle, in trading order
+}
+
+impl IndexedCycle {
+    pub fn contains_node(&self, node: usize) -> bool {
+        self.nodes.contains(&node)
+    }
+
+    // The edges of the cycle rotated to start at `node`, None if the cycle doesn't pass through it
+    pub fn edges_from(&self, node: usize) -> Option<Vec<usize>> {
+        let pos = self.nodes.iter().position(|&n| n == node)?;
+        Some(self.edges[pos..].iter().chain(self.edges[..pos].iter()).copied().collect())
+    }
+}
+
+pub struct CycleIndex {
+    pub max_len: usize,
+    pub cycles: Vec<IndexedCycle>,
+    pub cycles_by_edge: Vec<Vec<usize>>, // cycles_by_edge[e] is the indices of the cycles that contain edge e
+}
+
+impl CycleIndex {
+    // All simple cycles through `node` with at most max_len edges, each starting at `node`
+    pub fn through_node(graph: &StaticGraph, node: usize, max_len: usize) -> Self {
+        let mut cycles = vec![];
+        CycleEnumerator::new(graph, node, 0, max_len, &mut cycles).run();
+        Self::from_cycles(graph, max_len, cycles)
+    }
+
+    // All simple cycles of the graph with at most max_len edges, each starting at its smallest node
+    pub fn all(graph: &StaticGraph, max_len: usize) -> Self {
+        let mut cycles = vec![];
+        for root in 1..=graph.n_node() {
+            CycleEnumerator::new(graph, root, root + 1, max_len, &mut cycles).run();
+        }
+        Self::from_cycles(graph, max_len, cycles)
+    }
+
+    fn from_cycles(graph: &StaticGraph, max_len: usize, cycles: Vec<IndexedCycle>) -> Self {
+        let mut cycles_by_edge = vec![vec![]; graph.to.len()];
+        for (cycle_idx, cycle) in cycles.iter().enumerate() {
+            for &edge_idx in &cycle.edges {
+                cycles_by_edge[edge_idx].push(cycle_idx);
+            }
+        }
+        Self {
+            max_len,
+            cycles,
+            cycles_by_edge,
+        }
+    }
+
+    // The indices of the cycles that contain at least one of the edges, sorted and without duplicates
+    pub fn cycles_touching(&self, edges: &[usize]) -> Vec<usize> {
+        let mut cycle_ids: Vec<usize> = edges.iter()
+            .filter_map(|&edge_idx| self.cycles_by_edge.get(edge_idx))
+            .flatten()
+            .copied()
+            .collect();
+        cycle_ids.sort_unstable();
+        cycle_ids.dedup();
+        cycle_ids
+    }
+}
+
+// Depth-first enumeration of the simple paths from root back to root, only through nodes >= min_node
+struct CycleEnumerator<'a> {
+    graph: &'a StaticGraph,
+    root: usize,
+    min_node: usize,
+    max_len: usize,
+    on_path: Vec<bool>,
+    nodes: Vec<usize>,
+    edges: Vec<usize>,
+    cycles: &'a mut Vec<IndexedCycle>,
+}
+
+impl<'a> CycleEnumerator<'a> {
+    fn new(graph: &'a StaticGraph, root: usize, min_node: usize, max_len: usize, cycles: &'a mut Vec<IndexedCycle>) -> Self {
+        let mut on_path = vec![false; graph.head.len()];
+        on_path[root] = true;
+        Self {
+            graph,
+            root,
+            min_node,
+            max_len,
+            on_path,
+            nodes: vec![root],
+            edges: vec![],
+            cycles,
+        }
+    }
+
+    fn run(&mut self) {
+        if self.max_len > 0 {
+            self.visit(self.root);
+        }
+    }
+
+    fn visit(&mut self, node: usize) {
+        for (edge_idx, to_node) in self.graph.frozen().out_edges(node) {
+            if to_node == self.root {
+                let mut edges = self.edges.clone();
+                edges.push(edge_idx);
+                self.cycles.push(IndexedCycle {
+                    nodes: self.nodes.clone(),
+                    edges,
+                });
+            } else if to_node >= self.min_node && !self.on_path[to_node] && self.edges.len() + 1 < self.max_len {
+                self.on_path[to_node] = true;
+                self.nodes.push(to_node);
+                self.edges.push(edge_idx);
+                self.visit(to_node);
+                self.edges.pop();
+                self.nodes.pop();
+                self.on_path[to_node] = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::static_graph::DexFilter;
+
+    // A, B and C paired with each other, plus a second A -> B edge restricted to one DEX
+    fn triangle() -> StaticGraph {
+        GraphBuilder::new()
+            .test_nodes(&[("A", Some(6)), ("B", Some(6)), ("C", Some(6))])
+            .mint_pair("A", "B")
+            .mint_pair("B", "C")
+            .mint_pair("A", "C")
+            .mint_one_way_via("A", "B", DexFilter::only(&["Raydium"]))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn all_finds_each_cycle_once_from_its_smallest_node() {
+        let graph = triangle();
+        let index = CycleIndex::all(&graph, 3);
+        // 3 two-cycles and 2 triangles, plus A-B and A -> B -> C -> A again through the Raydium edge
+        assert_eq!(index.cycles.len(), 7);
+        let distinct: HashSet<&Vec<usize>> = index.cycles.iter().map(|cycle| &cycle.edges).collect();
+        assert_eq!(distinct.len(), index.cycles.len());
+        for cycle in &index.cycles {
+            assert_eq!(cycle.nodes[0], *cycle.nodes.iter().min().unwrap());
+            for (i, &edge_idx) in cycle.edges.iter().enumerate() {
+                assert_eq!(graph.edge_info[edge_idx].input_node, cycle.nodes[i]);
+                assert_eq!(graph.edge_info[edge_idx].output_node, cycle.nodes[(i + 1) % cycle.nodes.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn max_len_bounds_the_cycles() {
+        let index = CycleIndex::all(&triangle(), 2);
+        assert_eq!(index.cycles.len(), 4);
+        assert!(index.cycles.iter().all(|cycle| cycle.edges.len() == 2));
+    }
+
+    #[test]
+    fn through_node_starts_every_cycle_at_the_node() {
+        let graph = triangle();
+        let c = graph.node_id("C").unwrap();
+        let index = CycleIndex::through_node(&graph, c, 3);
+        // the A-B two-cycles don't pass through C
+        assert_eq!(index.cycles.len(), 5);
+        assert!(index.cycles.iter().all(|cycle| cycle.nodes[0] == c));
+    }
+
+    #[test]
+    fn edges_from_rotates_the_cycle() {
+        let graph = triangle();
+        let index = CycleIndex::all(&graph, 3);
+        let cycle = index.cycles.iter().find(|cycle| cycle.edges.len() == 3).unwrap();
+        let rotated = cycle.edges_from(cycle.nodes[1]).unwrap();
+        assert_eq!(rotated, [cycle.edges[1], cycle.edges[2], cycle.edges[0]]);
+        assert!(cycle.edges_from(0).is_none());
+    }
+
+    #[test]
+    fn cycles_by_edge_matches_the_cycles() {
+        let index = CycleIndex::all(&triangle(), 3);
+        for (cycle_idx, cycle) in index.cycles.iter().enumerate() {
+            for &edge_idx in &cycle.edges {
+                assert!(index.cycles_by_edge[edge_idx].contains(&cycle_idx));
+            }
+        }
+        let touching = index.cycles_touching(&[0, 0, 1]);
+        assert!(touching.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
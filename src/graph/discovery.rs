@@ -0,0 +1,279 @@
+// Discover the tokens Jupiter routes through but the graph doesn't watch
+// A quote between two watched tokens often goes through intermediate mints (route_plan[i].swap_info),
+// which is where the liquidity is. Each intermediate mint is counted once per quote it appears in,
+// and the most frequent ones are proposed as new nodes, paired with hub tokens of the graph.
+// Jupiter doesn't return the decimals of a mint, so a discovered token has unknown decimals and a symbol made
+// from its mint. Its edges are quoted once a quote into it gives it a price (see quote_sizing.rs), and the
+// searches only use raw amounts. Fix both in the topology file to keep the token for good.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::time::Instant;
+use serde::Serialize;
+use crate::graph::mutation::TopologyChange;
+use crate::graph::static_graph::{DexFilter, NodeInfo, StaticGraph};
+use crate::jupiter::quote::QuoteResponse;
+use crate::search::cached_search::QuoteSnapshot;
+
+#[derive(Debug, Clone)]
+pub struct DiscoveryConfig {
+    pub min_count: usize, // the number of quotes a mint must appear in before it is proposed
+    pub max_new_tokens: usize, // the number of mints proposed at most by one call of proposals
+    pub max_total_tokens: usize, // nothing is proposed once the graph has this many tokens, which bounds the quotes of a round
+    pub hubs: Vec<String>, // the mints a discovered token is paired with
+    pub auto_add: bool, // apply the proposals to the graph instead of only reporting them
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DiscoveredMint {
+    pub mint: String,
+    pub count: usize, // the number of quotes the mint appeared in
+    pub amm_labels: BTreeSet<String>, // the AMMs that swapped the mint
+    pub seen_with: BTreeSet<String>, // the watched mints it was swapped with directly
+}
+
+pub struct MintDiscovery {
+    pub config: DiscoveryConfig,
+    discovered: HashMap<String, DiscoveredMint>,
+    proposed: HashSet<String>, // the mints of the proposals passed to mark_proposed
+}
+
+impl MintDiscovery {
+    pub fn new(config: DiscoveryConfig) -> Self {
+        Self {
+            config,
+            discovered: HashMap::new(),
+            proposed: HashSet::new(),
+        }
+    }
+
+    // Count the mints of the route plan that are not nodes of the graph
+    pub fn observe(&mut self, graph: &StaticGraph, quote_rsp: &QuoteResponse) {
+        let mut seen_in_quote = HashSet::new();
+        for plan in &quote_rsp.route_plan {
+            let swap = &plan.swap_info;
+            for (mint, other) in [(&swap.input_mint, &swap.output_mint), (&swap.output_mint, &swap.input_mint)] {
+                if graph.node_id(mint).is_some() {
+                    continue;
+                }
+                let discovered = self.discovered.entry(mint.clone()).or_insert_with(|| DiscoveredMint {
+                    mint: mint.clone(),
+                    count: 0,
+                    amm_labels: BTreeSet::new(),
+                    seen_with: BTreeSet::new(),
+                });
+                if seen_in_quote.insert(mint.clone()) {
+                    discovered.count += 1;
+                }
+                discovered.amm_labels.insert(swap.label.clone());
+                if graph.node_id(other).is_some() {
+                    discovered.seen_with.insert(other.clone());
+                }
+            }
+        }
+    }
+
+    // Observe the quotes of the snapshot received at or after `since`, e.g. the start of the last update round,
+    // so a cached quote is only counted once
+    pub fn observe_snapshot(&mut self, snapshot: &QuoteSnapshot, since: Instant) {
+        for (quote_rsp, last_updated) in (0..snapshot.n_edge()).filter_map(|edge_idx| snapshot.quote(edge_idx)) {
+            if last_updated >= since {
+                self.observe(&snapshot.topology, quote_rsp);
+            }
+        }
+    }
+
+    // The discovered mints that are still not in the graph, most frequent first
+    pub fn ranked(&self, graph: &StaticGraph) -> Vec<&DiscoveredMint> {
+        let mut ranked: Vec<&DiscoveredMint> = self.discovered.values()
+            .filter(|d| graph.node_id(&d.mint).is_none())
+            .collect();
+        ranked.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.mint.cmp(&b.mint)));
+        ranked
+    }
+
+    // The changes that add the most frequent new mints, each paired with the hubs that are in the graph
+    // The same mints are proposed again until mark_proposed is called with the changes, so a change that
+    // was rejected is retried. The graph never grows past max_total_tokens through the proposals
+    pub fn proposals(&self, graph: &StaticGraph) -> Vec<TopologyChange> {
+        let hubs: Vec<&String> = self.config.hubs.iter().filter(|hub| graph.node_id(hub).is_some()).collect();
+        let room = self.config.max_total_tokens.saturating_sub(graph.n_node());
+        if hubs.is_empty() || room == 0 {
+            return vec![];
+        }
+        let mints: Vec<String> = self.ranked(graph).into_iter()
+            .filter(|d| d.count >= self.config.min_count && !self.proposed.contains(&d.mint))
+            .take(self.config.max_new_tokens.min(room))
+            .map(|d| d.mint.clone())
+            .collect();
+        let mut changes = vec![];
+        let mut symbols: HashSet<String> = graph.nodes.iter().map(|node| node.symbol.clone()).collect();
+        for mint in &mints {
+            // apply_changes rejects a symbol that is already used, the full mint is unique
+            let mut symbol = short_symbol(mint);
+            if !symbols.insert(symbol.clone()) {
+                symbol = mint.clone();
+                symbols.insert(symbol.clone());
+            }
+            changes.push(TopologyChange::AddToken(NodeInfo {
+                mint: mint.clone(),
+                symbol,
+                decimals: None,
+            }));
+            for hub in &hubs {
+                changes.push(TopologyChange::AddEdge { input_mint: (*hub).clone(), output_mint: mint.clone(), dex_filter: DexFilter::default() });
+                changes.push(TopologyChange::AddEdge { input_mint: mint.clone(), output_mint: (*hub).clone(), dex_filter: DexFilter::default() });
+            }
+        }
+        changes
+    }
+
+    // Never propose the tokens added by these changes again, call it once they are applied or reported
+    pub fn mark_proposed(&mut self, changes: &[TopologyChange]) {
+        for change in changes {
+            if let TopologyChange::AddToken(node) = change {
+                self.proposed.insert(node.mint.clone());
+            }
+        }
+    }
+}
+
+// The mints of the n nodes with the most out edges, a natural choice of hubs
+pub fn hubs_by_degree(graph: &StaticGraph, n: usize) -> Vec<String> {
+    let mut nodes: Vec<usize> = (1..=graph.n_node()).collect();
+    nodes.sort_by_key(|&node| std::cmp::Reverse(graph.frozen().out_edge_ids(node).len()));
+    nodes.into_iter().take(n).map(|node| graph.node(node).mint.clone()).collect()
+}
+
+// "AbCd..wXyZ"
+fn short_symbol(mint: &str) -> String {
+    if mint.len() <= 10 {
+        return mint.to_string();
+    }
+    format!("{}..{}", &mint[..4], &mint[mint.len() - 4..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::builder::GraphBuilder;
+    use crate::jupiter::quote::{RoutePlan, SwapInfo};
+
+    // A quote whose route swaps through the (input mint, output mint, AMM) hops
+    fn routed_quote(hops: &[(&str, &str, &str)]) -> QuoteResponse {
+        let mut quote_rsp = QuoteResponse::for_test(hops[0].0, hops[hops.len() - 1].1, 1000, 1000);
+        quote_rsp.route_plan = hops.iter().map(|&(input_mint, output_mint, label)| RoutePlan {
+            swap_info: SwapInfo {
+                amm_key: String::new(),
+                label: label.to_string(),
+                input_mint: input_mint.to_string(),
+                output_mint: output_mint.to_string(),
+                in_amount: "1000".to_string(),
+                out_amount: "1000".to_string(),
+                fee_amount: "0".to_string(),
+                fee_mint: input_mint.to_string(),
+            },
+            percent: 100,
+        }).collect();
+        quote_rsp
+    }
+
+    fn graph() -> StaticGraph {
+        GraphBuilder::new()
+            .test_nodes(&[("SOL", Some(9)), ("USDC", Some(6)), ("BONK", Some(5))])
+            .mint_pair("SOL", "USDC")
+            .mint_pair("SOL", "BONK")
+            .build()
+            .unwrap()
+    }
+
+    fn discovery(mints: &[&str]) -> MintDiscovery {
+        let mut discovery = MintDiscovery::new(DiscoveryConfig {
+            min_count: 2,
+            max_new_tokens: 10,
+            max_total_tokens: 10,
+            hubs: vec!["SOL".to_string()],
+            auto_add: true,
+        });
+        for (i, mint) in mints.iter().enumerate() {
+            discovery.discovered.insert(mint.to_string(), DiscoveredMint {
+                mint: mint.to_string(),
+                count: 2 + i,
+                amm_labels: BTreeSet::new(),
+                seen_with: BTreeSet::new(),
+            });
+        }
+        discovery
+    }
+
+    #[test]
+    fn proposals_never_reuse_a_symbol() {
+        let graph = GraphBuilder::new().test_nodes(&[("SOL", Some(9)), ("Abcd..wxyz", Some(6))]).build().unwrap();
+        let discovery = discovery(&["Abcd1111wxyz", "Abcd2222wxyz", "Efgh3333wxyz"]);
+        let symbols: Vec<String> = discovery.proposals(&graph).into_iter()
+            .filter_map(|change| match change {
+                TopologyChange::AddToken(node) => Some(node.symbol),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(symbols, ["Efgh..wxyz", "Abcd2222wxyz", "Abcd1111wxyz"]);
+    }
+
+    #[test]
+    fn observe_counts_each_new_mint_once_per_quote() {
+        let graph = graph();
+        let mut discovery = discovery(&[]);
+        discovery.observe(&graph, &routed_quote(&[("SOL", "JUP", "Orca"), ("JUP", "X", "Raydium"), ("X", "USDC", "Orca")]));
+        discovery.observe(&graph, &routed_quote(&[("SOL", "JUP", "Meteora"), ("JUP", "USDC", "Meteora")]));
+        let ranked = discovery.ranked(&graph);
+        assert_eq!(ranked.iter().map(|d| (d.mint.as_str(), d.count)).collect::<Vec<_>>(), [("JUP", 2), ("X", 1)]);
+        assert_eq!(ranked[0].amm_labels.iter().collect::<Vec<_>>(), ["Meteora", "Orca", "Raydium"]);
+        assert_eq!(ranked[0].seen_with.iter().collect::<Vec<_>>(), ["SOL", "USDC"]);
+        assert_eq!(ranked[1].seen_with.iter().collect::<Vec<_>>(), ["USDC"]);
+    }
+
+    #[test]
+    fn proposals_pair_the_frequent_mints_with_the_hubs() {
+        let graph = graph();
+        let mut discovery = discovery(&[]);
+        discovery.config.hubs = vec!["SOL".to_string(), "USDC".to_string(), "NOT_A_NODE".to_string()];
+        for _ in 0..2 {
+            discovery.observe(&graph, &routed_quote(&[("SOL", "JUP", "Orca"), ("JUP", "USDC", "Orca")]));
+        }
+        discovery.observe(&graph, &routed_quote(&[("SOL", "X", "Orca"), ("X", "USDC", "Orca")]));
+
+        // X was seen once, below min_count
+        let changes = discovery.proposals(&graph);
+        assert_eq!(changes.len(), 5);
+        assert!(matches!(&changes[0], TopologyChange::AddToken(node) if node.mint == "JUP" && node.decimals.is_none()));
+        let edges: Vec<(&str, &str)> = changes[1..].iter().filter_map(|change| match change {
+            TopologyChange::AddEdge { input_mint, output_mint, .. } => Some((input_mint.as_str(), output_mint.as_str())),
+            _ => None,
+        }).collect();
+        assert_eq!(edges, [("SOL", "JUP"), ("JUP", "SOL"), ("USDC", "JUP"), ("JUP", "USDC")]);
+
+        discovery.mark_proposed(&changes);
+        assert!(discovery.proposals(&graph).is_empty());
+    }
+
+    #[test]
+    fn proposals_stop_at_max_total_tokens() {
+        let graph = graph();
+        let mut discovery = discovery(&["A", "B", "C"]);
+        discovery.config.max_total_tokens = 5;
+        let tokens = |changes: Vec<TopologyChange>| changes.into_iter()
+            .filter(|change| matches!(change, TopologyChange::AddToken(_)))
+            .count();
+        assert_eq!(tokens(discovery.proposals(&graph)), 2);
+        discovery.config.max_new_tokens = 1;
+        assert_eq!(tokens(discovery.proposals(&graph)), 1);
+        discovery.config.max_total_tokens = 3;
+        assert!(discovery.proposals(&graph).is_empty());
+    }
+
+    #[test]
+    fn hubs_are_the_nodes_with_the_most_out_edges() {
+        assert_eq!(hubs_by_degree(&graph(), 1), ["SOL"]);
+        assert_eq!(hubs_by_degree(&graph(), 5).len(), 3);
+        assert_eq!(short_symbol("So11111111111111111111111111111111111111112"), "So11..1112");
+    }
+}
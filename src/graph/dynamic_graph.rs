@@ -1,113 +1,210 @@
 // The dynamic attribute layer of the graph
 
-use std::sync::{Arc, RwLock};
-use std::time::Instant;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 use std::future::Future;
 use tokio::task::JoinSet;
+use crate::graph::attr_table::{AttrStore, AttrTable};
+use crate::graph::pruning::StartCyclePruning;
+use crate::graph::quote_ladder::QuoteLadder;
 use crate::graph::static_graph::StaticGraph;
-use crate::jupiter::quote::QuoteResponse;
+use crate::jupiter::quote::{QuoteParams, QuoteResponse};
 
 pub struct DynamicGraph {
-    pub topology: Arc<StaticGraph>,
-    pub start_node: usize,
-    pub start_amount: u64,
-    pub attr: Vec<Arc<RwLock<EdgeAttribute>>>,
+    // the topology, the start node, the pruning and the attributes of the edges, read a consistent table of all of them
+    // with attrs.load(). A clone of the Arc is a handle to read them from another task, without blocking the updater
+    pub attrs: Arc<AttrStore>,
+    pub ladder_factors: Vec<f64>, // multiples of the quote amount quoted with it as a ladder, e.g. [0.1, 10.0], empty for no ladder
+    pub reference_prices: HashMap<String, f64>, // mint -> price of one whole token, sets the quote amount of its edges, see quote_sizing.rs
 }
 
-#[allow(dead_code)]
+#[derive(Clone)]
 pub struct EdgeAttribute {
     pub quote_response: Option<QuoteResponse>,
     pub last_updated: Instant, // last update time in milliseconds
+    pub updated_at: SystemTime, // the wall-clock time of last_updated, which can be persisted across restarts
+    pub ladder: Option<QuoteLadder>, // the quotes at the ladder sizes, refreshed together with quote_response
 }
 
 impl EdgeAttribute {
-    #[allow(dead_code)]
     pub fn new() -> Self {
         Self {
             quote_response: None,
             // For convenience of initialization, set a smaller time, 30 minutes ago
             // So that when update_edge_attr is called for the first time, all edges will be updated
             last_updated: Instant::now() - std::time::Duration::from_secs(1800),
+            updated_at: SystemTime::now() - std::time::Duration::from_secs(1800),
+            ladder: None,
         }
     }
 }
 
+impl Default for EdgeAttribute {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DynamicGraph {
     pub fn new(topology: Arc<StaticGraph>, start_node: usize, start_amount: u64) -> Self {
-        let n_edge = topology.to.len();
         Self {
-            topology,
-            start_node,
-            start_amount,
-            attr: vec![Arc::new(RwLock::new(EdgeAttribute::new())); n_edge],
+            attrs: Arc::new(AttrStore::new(topology, start_node, start_amount)),
+            ladder_factors: vec![],
+            reference_prices: HashMap::new(),
+        }
+    }
+
+    // The current topology, a table of attrs.load() pairs it with the attributes of its edges
+    pub fn topology(&self) -> Arc<StaticGraph> {
+        self.attrs.load().topology.clone()
+    }
+
+    pub fn start_node(&self) -> usize {
+        self.attrs.load().start_node
+    }
+
+    pub fn start_amount(&self) -> u64 {
+        self.attrs.load().start_amount
+    }
+
+    // Incremented each time the topology is replaced, see mutation.rs
+    pub fn version(&self) -> u64 {
+        self.attrs.load().version
+    }
+
+    // The amounts an edge of the table is quoted at, the quote amount first and then the ladder sizes
+    // Empty if the edge can't be quoted yet, see quote_sizing.rs
+    fn quote_amounts(&self, table: &AttrTable, edge_idx: usize) -> Vec<u64> {
+        let Some(quote_amount) = self.quote_amount_in(table, edge_idx) else {
+            return vec![];
+        };
+        let mut amounts = vec![quote_amount];
+        for factor in &self.ladder_factors {
+            let amount = (quote_amount as f64 * factor) as u64;
+            if amount > 0 && !amounts.contains(&amount) {
+                amounts.push(amount);
+            }
         }
+        amounts
+    }
+
+    // Stop updating the edges that can't be on a cycle through the start node with at most max_path_len edges
+    // The pruning is dropped when the topology changes, call prune again after apply_changes
+    pub fn prune(&mut self, max_path_len: usize) -> StartCyclePruning {
+        let table = self.attrs.load();
+        let pruning = StartCyclePruning::analyze(&table.topology, table.start_node, max_path_len);
+        self.attrs.set_live_edges(table.version, Some(pruning.live_edges.clone()));
+        pruning
     }
-    
-    pub async fn update_edge_attr<F, Fut>(&mut self, min_millis: u128, max_concurrency: usize, update_fn: F)
-        -> Vec<Result<(), Box<dyn std::error::Error + Send>>>
+
+    pub fn is_live(&self, edge_idx: usize) -> bool {
+        self.attrs.load().is_live(edge_idx)
+    }
+
+    // Quote the live edges not updated for min_millis, at most max_concurrency at a time
+    // The edges and their amounts are chosen when it is called, and the returned future only holds the store,
+    // so the graph can be changed (e.g. with apply_changes) while the quotes run. The quotes are published
+    // in batches, each quote landing on its edge in the topology current at that time, see AttrStore::set_many
+    pub fn update_edge_attr<F, Fut>(&self, min_millis: u128, max_concurrency: usize, update_fn: F)
+        -> impl Future<Output = Vec<Result<(), Box<dyn std::error::Error + Send>>>> + Send + use<F, Fut>
     where
-        F: Fn(u64, String, String) -> Fut + Send + Sync + Clone + 'static,
+        F: Fn(QuoteParams) -> Fut + Send + Sync + Clone + 'static,
         Fut: Future<Output = Result<QuoteResponse, Box<dyn std::error::Error + Send>>> + Send + 'static,
     {
         // for each edge, check whether the current time > the last update time + min_millis
-        let mut edges_to_update: Vec<(usize, Instant)> = Vec::with_capacity(self.attr.len());
-        for (i, attr) in self.attr.iter().enumerate() {
-            if let Ok(attr_guard) = attr.read() {
-                let last_update = attr_guard.last_updated;
-                // eprintln!("The last update time of edge {} is {:?}", i, last_update.elapsed());
-                if last_update.elapsed().as_millis() > min_millis {
-                    edges_to_update.push((i, last_update));
-                }
+        let table = self.attrs.load();
+        let mut edges_to_update: Vec<(usize, Instant)> = Vec::with_capacity(table.len());
+        for (i, attr) in table.attrs.iter().enumerate() {
+            if !table.is_live(i) {
+                continue;
+            }
+            let last_update = attr.last_updated;
+            // eprintln!("The last update time of edge {} is {:?}", i, last_update.elapsed());
+            if last_update.elapsed().as_millis() > min_millis {
+                edges_to_update.push((i, last_update));
             }
         }
-        eprintln!("{} edges need to be updated", edges_to_update.len());
-        edges_to_update.sort_by(|a, b| a.1.cmp(&b.1));
-
-        let semaphore = Arc::new(tokio::sync::Semaphore::new(max_concurrency));
-        let mut join_set = JoinSet::new();
-        for (edge_idx, _last_update) in edges_to_update {
-            let attr = self.attr[edge_idx].clone();
-            let semaphore = semaphore.clone();
-            let update_fn = update_fn.clone();
-            
-            let static_edge = &self.topology.edge_info[edge_idx];
-            let edge_input_mint = static_edge.input_mint.clone();
-            let edge_output_mint = static_edge.output_mint.clone();
-            let start_amount = self.start_amount;
-            
-            
-            // spawn a task to update the edge attribute
-            join_set.spawn(async move {
-                // acquire a permit from the semaphore
-                let _permit = semaphore.clone().acquire_owned().await.unwrap();
-                
-                // call the update function
-                match update_fn(start_amount, edge_input_mint, edge_output_mint).await {
-                    Ok(quote_response) => {
-                        // update the last update time
-                        if let Ok(mut guard) = attr.write() {
-                            guard.quote_response = Some(quote_response);
-                            guard.last_updated = Instant::now();
-                            // eprintln!("Edge last updated at {:?}.", guard.last_updated.elapsed());
+        edges_to_update.sort_by_key(|a| a.1);
+
+        // a token with unknown decimals and no price has no quote amount yet
+        let planned: Vec<(usize, Vec<QuoteParams>)> = edges_to_update.into_iter()
+            .map(|(edge_idx, _)| (edge_idx, self.quote_amounts(&table, edge_idx)))
+            .filter(|(_, amounts)| !amounts.is_empty())
+            .map(|(edge_idx, amounts)| {
+                let params = amounts.iter().map(|&amount| table.topology.quote_params(edge_idx, amount)).collect();
+                (edge_idx, params)
+            })
+            .collect();
+        eprintln!("{} edges need to be updated", planned.len());
+        let store = self.attrs.clone();
+        let topology = table.topology.clone();
+
+        async move {
+            let semaphore = Arc::new(tokio::sync::Semaphore::new(max_concurrency));
+            let mut join_set = JoinSet::new();
+            for (edge_idx, mut quote_params) in planned {
+                let semaphore = semaphore.clone();
+                let update_fn = update_fn.clone();
+                let ladder_params = quote_params.split_off(1);
+                let quote_params = quote_params.pop().unwrap();
+
+                // spawn a task to update the edge attribute
+                join_set.spawn(async move {
+                    // acquire a permit from the semaphore, the ladder is quoted under the same permit
+                    let _permit = semaphore.clone().acquire_owned().await.unwrap();
+
+                    // call the update function
+                    match update_fn(quote_params).await {
+                        Ok(quote_response) => {
+                            let ladder = if ladder_params.is_empty() {
+                                None
+                            } else {
+                                let mut ladder_quotes = vec![quote_response.clone()];
+                                for params in ladder_params {
+                                    match update_fn(params).await {
+                                        Ok(rung) => ladder_quotes.push(rung),
+                                        Err(e) => eprintln!("Error updating the ladder of edge {}: {}", edge_idx, e),
+                                    }
+                                }
+                                QuoteLadder::from_quotes(&ladder_quotes)
+                            };
+                            Ok((edge_idx, EdgeAttribute {
+                                quote_response: Some(quote_response),
+                                last_updated: Instant::now(),
+                                updated_at: SystemTime::now(),
+                                ladder,
+                            }))
+                        },
+                        Err(e) => {
+                            eprintln!("Error updating edge {}: {}", edge_idx, e);
+                            Err(e)
                         }
-                        Ok(())
-                    },
-                    Err(e) => {
-                        eprintln!("Error updating edge {}: {}", edge_idx, e);
-                        Err(e)
                     }
-                }
-            });
-        }
+                });
+            }
 
-        // collect the result
-        let mut results = Vec::new();
-        while let Some(join_result) = join_set.join_next().await {
-            match join_result {
-                Ok(task_result) => results.push(task_result),
-                Err(e) => eprintln!("Task panicked: {}", e)
+            // collect the result, and publish the new attributes once every max_concurrency quotes, so a long round
+            // is visible as it goes without copying the table for each quote
+            let mut results = Vec::new();
+            let mut pending = Vec::with_capacity(max_concurrency);
+            while let Some(join_result) = join_set.join_next().await {
+                match join_result {
+                    Ok(Ok(update)) => {
+                        pending.push(update);
+                        results.push(Ok(()));
+                        if pending.len() >= max_concurrency {
+                            store.set_many(&topology, std::mem::take(&mut pending));
+                        }
+                    },
+                    Ok(Err(e)) => results.push(Err(e)),
+                    Err(e) => eprintln!("Task panicked: {}", e)
+                }
             }
+            if !pending.is_empty() {
+                store.set_many(&topology, pending);
+            }
+            results
         }
-        results
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,270 @@
+// Export a graph for inspection, as a JSON document or as Graphviz DOT (e.g. `dot -Tsvg graph.dot > graph.svg`)
+// A StaticGraph is exported with the token symbols only, a DynamicGraph adds the cached quote of each edge:
+// the exchange rate in whole tokens, the age of the quote and its price impact.
+// The edges of the given opportunities are highlighted, so the cycles a search picked stand out.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::Instant;
+use serde::Serialize;
+use crate::graph::dynamic_graph::DynamicGraph;
+use crate::graph::static_graph::StaticGraph;
+use crate::search::cached_search::QuoteSnapshot;
+use crate::search::opportunity::Opportunity;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GraphExport {
+    pub start_node: Option<usize>,
+    pub nodes: Vec<NodeExport>,
+    pub edges: Vec<EdgeExport>,
+    pub cycles: Vec<CycleExport>, // the highlighted opportunities
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct NodeExport {
+    pub id: usize,
+    pub symbol: String,
+    pub mint: String,
+    pub decimals: Option<u8>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EdgeExport {
+    pub id: usize,
+    pub from: usize,
+    pub to: usize,
+    pub dexes: String, // the DEX restriction of the edge, empty when unrestricted
+    pub quote: Option<EdgeQuote>, // None for a StaticGraph, or an edge without a cached quote
+    pub highlighted: bool, // the edge is on one of the highlighted cycles
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EdgeQuote {
+    pub in_amount: u64,
+    pub out_amount: u64,
+    pub rate: Option<f64>, // whole output tokens per whole input token, None if the decimals of a token are unknown
+    pub age_ms: u64,
+    pub price_impact_pct: String, // a fraction as returned by Jupiter, 0.01 is 1%
+    pub amm_labels: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CycleExport {
+    pub edges: Vec<usize>,
+    pub symbols: Vec<String>,
+    pub start_amount: u64,
+    pub end_amount: u64,
+    pub gross_profit: i128,
+    pub roi_bps: f64,
+}
+
+impl GraphExport {
+    pub fn from_static(graph: &StaticGraph, start_node: Option<usize>, highlights: &[Opportunity]) -> Self {
+        let highlighted: HashSet<usize> = highlights.iter().flat_map(|o| o.edge_ids()).collect();
+        let nodes = (1..=graph.n_node()).map(|id| {
+            let node = graph.node(id);
+            NodeExport {
+                id,
+                symbol: node.symbol.clone(),
+                mint: node.mint.clone(),
+                decimals: node.decimals,
+            }
+        }).collect();
+        let edges = (0..graph.n_edge()).map(|edge_idx| {
+            let (from, to, info) = graph.edge(edge_idx);
+            EdgeExport {
+                id: edge_idx,
+                from,
+                to,
+                dexes: info.dex_filter.label(),
+                quote: None,
+                highlighted: highlighted.contains(&edge_idx),
+            }
+        }).collect();
+        let cycles = highlights.iter().map(|opportunity| CycleExport {
+            edges: opportunity.edge_ids(),
+            symbols: opportunity.symbols(graph).into_iter().map(String::from).collect(),
+            start_amount: opportunity.start_amount,
+            end_amount: opportunity.end_amount,
+            gross_profit: opportunity.gross_profit,
+            roi_bps: opportunity.roi_bps,
+        }).collect();
+        Self {
+            start_node,
+            nodes,
+            edges,
+            cycles,
+        }
+    }
+
+    pub fn from_dynamic(graph: &DynamicGraph, highlights: &[Opportunity]) -> Self {
+        Self::from_snapshot(&QuoteSnapshot::capture(graph), highlights)
+    }
+
+    pub fn from_snapshot(snapshot: &QuoteSnapshot, highlights: &[Opportunity]) -> Self {
+        let graph = &snapshot.topology;
+        let now = Instant::now();
+        let mut export = Self::from_static(graph, Some(snapshot.start_node), highlights);
+        for edge in &mut export.edges {
+            let Some((quote_rsp, last_updated)) = snapshot.quote(edge.id) else {
+                continue;
+            };
+            let (Ok(in_amount), Ok(out_amount)) = (quote_rsp.in_amount.parse::<u64>(), quote_rsp.out_amount.parse::<u64>()) else {
+                continue;
+            };
+            if in_amount == 0 {
+                continue;
+            }
+            let rate = match (graph.node(edge.from).unit(), graph.node(edge.to).unit()) {
+                (Some(in_unit), Some(out_unit)) => Some((out_amount as f64 / out_unit) / (in_amount as f64 / in_unit)),
+                _ => None,
+            };
+            edge.quote = Some(EdgeQuote {
+                in_amount,
+                out_amount,
+                rate,
+                age_ms: now.saturating_duration_since(last_updated).as_millis() as u64,
+                price_impact_pct: quote_rsp.price_impact_pct.clone(),
+                amm_labels: quote_rsp.route_plan.iter().map(|plan| plan.swap_info.label.clone()).collect(),
+            });
+        }
+        export
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph dexcreeper {{").unwrap();
+        writeln!(dot, "    rankdir=LR;").unwrap();
+        writeln!(dot, "    node [shape=ellipse];").unwrap();
+        for node in &self.nodes {
+            let shape = if self.start_node == Some(node.id) { " shape=doublecircle" } else { "" };
+            writeln!(dot, "    n{} [label=\"{}\"{}];", node.id, escape(&node.symbol), shape).unwrap();
+        }
+        // a StaticGraph has no quote at all, only a DynamicGraph can have edges without a quote
+        let quoted = self.edges.iter().any(|e| e.quote.is_some());
+        for edge in &self.edges {
+            let mut label = format!("e{}", edge.id);
+            if !edge.dexes.is_empty() {
+                write!(label, " [{}]", escape(&edge.dexes)).unwrap();
+            }
+            if let Some(quote) = &edge.quote {
+                if let Some(rate) = quote.rate {
+                    write!(label, "\\n{:.6}", rate).unwrap();
+                }
+                write!(label, "\\n{} ms", quote.age_ms).unwrap();
+                // Jupiter's priceImpactPct is a fraction, 0.01 is 1%
+                match quote.price_impact_pct.parse::<f64>() {
+                    Ok(impact) => write!(label, ", impact {:.4}%", impact * 100.0).unwrap(),
+                    Err(_) => write!(label, ", impact {}", escape(&quote.price_impact_pct)).unwrap(),
+                }
+            }
+            let style = if edge.highlighted {
+                " color=red penwidth=2.5 fontcolor=red"
+            } else if quoted && edge.quote.is_none() {
+                // the searches can't use this edge
+                " style=dashed color=gray"
+            } else {
+                ""
+            };
+            // the parts of the label are escaped when added, so its \n line breaks are kept
+            writeln!(dot, "    n{} -> n{} [label=\"{}\"{}];", edge.from, edge.to, label, style).unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+// Escape a string for a double-quoted DOT label, a newline becomes a \n line break
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use super::*;
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::dynamic_graph::EdgeAttribute;
+    use crate::graph::static_graph::{DexFilter, NodeInfo};
+    use crate::jupiter::quote::QuoteResponse;
+    use crate::search::opportunity::Leg;
+
+    // WSOL (9 decimals) <-> USDC (6), a Raydium-only WSOL -> USDC edge, and USDC -> X, a token with unknown
+    // decimals whose symbol "X\ has to be escaped in DOT
+    fn graph() -> DynamicGraph {
+        let static_graph = GraphBuilder::new()
+            .test_nodes(&[("WSOL", Some(9)), ("USDC", Some(6))])
+            .node(NodeInfo { mint: "X".to_string(), symbol: "\"X\\".to_string(), decimals: None })
+            .mint_pair("WSOL", "USDC")
+            .mint_one_way_via("WSOL", "USDC", DexFilter::only(&["Raydium"]))
+            .mint_one_way("USDC", "X")
+            .build()
+            .unwrap();
+        let start_node = static_graph.node_id("WSOL").unwrap();
+        DynamicGraph::new(Arc::new(static_graph), start_node, 1_000_000_000)
+    }
+
+    fn quote(graph: &DynamicGraph, from: &str, to: &str, in_amount: u64, out_amount: u64) -> usize {
+        let topology = graph.topology();
+        let edge_idx = topology.find_edge(from, to, &DexFilter::default()).unwrap();
+        let mut quote_response = QuoteResponse::for_test(from, to, in_amount, out_amount);
+        quote_response.price_impact_pct = "0.0123".to_string();
+        graph.attrs.set_many(&topology, vec![(edge_idx, EdgeAttribute {
+            quote_response: Some(quote_response),
+            last_updated: Instant::now() - Duration::from_secs(2),
+            ..EdgeAttribute::new()
+        })]);
+        edge_idx
+    }
+
+    #[test]
+    fn a_snapshot_exports_the_rates_in_whole_tokens() {
+        let graph = graph();
+        // 1 WSOL for 150 USDC, and 10 USDC for 5 units of a token without decimals
+        let wsol_usdc = quote(&graph, "WSOL", "USDC", 1_000_000_000, 150_000_000);
+        let usdc_x = quote(&graph, "USDC", "X", 10_000_000, 5);
+        let export = GraphExport::from_dynamic(&graph, &[]);
+        assert_eq!(export.start_node, Some(1));
+        assert_eq!(export.nodes.len(), 3);
+        let edge_quote = export.edges[wsol_usdc].quote.as_ref().unwrap();
+        assert_eq!(edge_quote.rate, Some(150.0));
+        assert!(edge_quote.age_ms >= 2_000);
+        assert_eq!(export.edges[usdc_x].quote.as_ref().unwrap().rate, None);
+        let raydium = graph.topology().find_edge("WSOL", "USDC", &DexFilter::only(&["Raydium"])).unwrap();
+        assert_eq!(export.edges[raydium].dexes, "Raydium");
+        assert!(export.edges[raydium].quote.is_none());
+
+        let json: serde_json::Value = serde_json::from_str(&export.to_json().unwrap()).unwrap();
+        assert_eq!(json["edges"][wsol_usdc]["quote"]["rate"], 150.0);
+        assert_eq!(json["nodes"][2]["decimals"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn dot_escapes_the_labels_and_highlights_the_cycles() {
+        let graph = graph();
+        let wsol_usdc = quote(&graph, "WSOL", "USDC", 1_000_000_000, 150_000_000);
+        let usdc_wsol = quote(&graph, "USDC", "WSOL", 150_000_000, 1_010_000_000);
+        let legs = [wsol_usdc, usdc_wsol].map(|edge_idx| {
+            let attr = graph.attrs.load().get(edge_idx).clone();
+            Leg::from_quote(edge_idx, attr.quote_response.as_ref().unwrap(), SystemTime::now()).unwrap()
+        });
+        let export = GraphExport::from_dynamic(&graph, &[Opportunity::new(legs.to_vec())]);
+        assert_eq!(export.cycles[0].symbols, ["WSOL", "USDC", "WSOL"]);
+        assert_eq!(export.cycles[0].gross_profit, 10_000_000);
+
+        let dot = export.to_dot();
+        assert!(dot.starts_with("digraph dexcreeper {\n"));
+        assert!(dot.contains("n1 [label=\"WSOL\" shape=doublecircle];"));
+        assert!(dot.contains("n3 [label=\"\\\"X\\\\\"];"));
+        assert!(dot.contains(&format!("n1 -> n2 [label=\"e{}\\n150.000000\\n", wsol_usdc)));
+        assert!(dot.contains(", impact 1.2300%\" color=red penwidth=2.5 fontcolor=red];"));
+        // the unquoted edges are dashed once the graph has quotes
+        assert!(dot.contains("[Raydium]\" style=dashed color=gray];"));
+        assert!(GraphExport::from_static(&graph.topology(), None, &[]).to_dot().lines().all(|line| !line.contains("dashed")));
+    }
+}
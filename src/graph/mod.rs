@@ -1,3 +1,16 @@
 pub mod static_graph;
 pub mod dynamic_graph;
-pub mod schedule_update;
\ No newline at end of file
+pub mod schedule_update;
+pub mod cycle_index;
+pub mod builder;
+pub mod csr_graph;
+pub mod export;
+pub mod topology_file;
+pub mod validate;
+pub mod mutation;
+pub mod discovery;
+pub mod pruning;
+pub mod persist;
+pub mod attr_table;
+pub mod quote_ladder;
+pub mod quote_sizing;
\ No newline at end of file
@@ -0,0 +1,285 @@
+// Add and remove tokens and edges of a running DynamicGraph without losing its warm quotes
+// The updater of schedule_update.rs applies the changes it receives as they come, during an update round too,
+// exe_schedule_update --changes-stdin reads them from stdin, one JSON array of changes per line:
+// [{"add_token": {"mint": "...", "symbol": "JUP", "decimals": 6}}, {"add_edge": {"input_mint": "...", "output_mint": "..."}}]
+// The StaticGraph behind the Arc is never modified: a change builds a new StaticGraph and swaps it in,
+// so anything holding the previous Arc (a QuoteSnapshot, a search in progress) keeps a consistent topology.
+// Node ids are compact, so removing a token renumbers the nodes and edges after it. The kept edges are added
+// back in the order of their old ids, which keeps the chain forward star order, and keep their EdgeAttribute.
+// New edges get a fresh EdgeAttribute, which looks 30 minutes old, so the next update round quotes them first.
+// A change can be applied while an update round runs: the future of update_edge_attr doesn't borrow the graph,
+// and the quotes it publishes after the change are moved to the same edge (same mints and DEX filter) in the new
+// topology, or dropped if the edge was removed. The topology is only replaced here, with &mut self, so two changes
+// can't race each other.
+
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::graph::dynamic_graph::DynamicGraph;
+use crate::graph::static_graph::{DexFilter, GraphError, NodeInfo, StaticGraph};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum TopologyChange {
+    AddToken(NodeInfo),
+    RemoveToken(String), // mint, the edges from and to the token are removed too
+    AddEdge {
+        input_mint: String,
+        output_mint: String,
+        #[serde(flatten)]
+        dex_filter: DexFilter,
+    },
+    RemoveEdge {
+        input_mint: String,
+        output_mint: String,
+        #[serde(flatten)]
+        dex_filter: DexFilter,
+    },
+}
+
+// (input mint, output mint, DEX filter, id of the edge in the current topology if it already exists)
+type PendingEdge = (String, String, DexFilter, Option<usize>);
+
+impl DynamicGraph {
+    // Apply the changes in order, all or none of them
+    // Returns the ids of the new edges in the new topology
+    pub fn apply_changes(&mut self, changes: &[TopologyChange]) -> Result<Vec<usize>, GraphError> {
+        let table = self.attrs.load();
+        let old = &table.topology;
+        let start_mint = old.node(table.start_node).mint.clone();
+        let mut nodes: Vec<NodeInfo> = old.nodes[1..].to_vec();
+        let mut edges: Vec<PendingEdge> = (0..old.n_edge())
+            .map(|edge_idx| (
+                old.input_mint(edge_idx).to_string(),
+                old.output_mint(edge_idx).to_string(),
+                old.edge_info[edge_idx].dex_filter.clone(),
+                Some(edge_idx),
+            ))
+            .collect();
+
+        for change in changes {
+            match change {
+                TopologyChange::AddToken(node) => {
+                    if nodes.iter().any(|n| n.mint == node.mint) {
+                        return Err(GraphError::DuplicateMint(node.mint.clone()));
+                    }
+                    // a snapshot of persist.rs with two tokens of the same symbol could never be loaded again
+                    if nodes.iter().any(|n| n.symbol == node.symbol) {
+                        return Err(GraphError::DuplicateSymbol(node.symbol.clone()));
+                    }
+                    nodes.push(node.clone());
+                }
+                TopologyChange::RemoveToken(mint) => {
+                    if *mint == start_mint {
+                        return Err(GraphError::RemoveStartNode(mint.clone()));
+                    }
+                    if !nodes.iter().any(|n| n.mint == *mint) {
+                        return Err(GraphError::UnknownMint(mint.clone()));
+                    }
+                    nodes.retain(|n| n.mint != *mint);
+                    edges.retain(|(input_mint, output_mint, _, _)| input_mint != mint && output_mint != mint);
+                }
+                TopologyChange::AddEdge { input_mint, output_mint, dex_filter } => {
+                    edges.push((input_mint.clone(), output_mint.clone(), dex_filter.clone(), None));
+                }
+                TopologyChange::RemoveEdge { input_mint, output_mint, dex_filter } => {
+                    let pos = edges.iter()
+                        .position(|(i, o, f, _)| i == input_mint && o == output_mint && f == dex_filter)
+                        .ok_or_else(|| GraphError::MissingEdge { input_mint: input_mint.clone(), output_mint: output_mint.clone() })?;
+                    edges.remove(pos);
+                }
+            }
+        }
+
+        // add_edge_via checks the new edges, nothing is swapped in if one of them is invalid
+        let mut graph = StaticGraph::new(nodes);
+        let mut old_ids = Vec::with_capacity(edges.len());
+        let mut new_edges = vec![];
+        for (input_mint, output_mint, dex_filter, old_id) in edges {
+            let from = graph.node_id(&input_mint).ok_or(GraphError::UnknownMint(input_mint))?;
+            let to = graph.node_id(&output_mint).ok_or(GraphError::UnknownMint(output_mint))?;
+            let edge_idx = graph.add_edge_via(from, to, dex_filter)?;
+            old_ids.push(old_id);
+            if old_id.is_none() {
+                new_edges.push(edge_idx);
+            }
+        }
+
+        // the kept edges keep their attributes, including the quotes published since `table` was loaded,
+        // and the pruning is dropped since the edge ids changed
+        let start_node = graph.node_id(&start_mint).unwrap();
+        self.attrs.set_topology(Arc::new(graph), start_node, &old_ids);
+        Ok(new_edges)
+    }
+
+    pub fn add_token(&mut self, node: NodeInfo) -> Result<(), GraphError> {
+        self.apply_changes(&[TopologyChange::AddToken(node)]).map(|_| ())
+    }
+
+    pub fn remove_token(&mut self, mint: &str) -> Result<(), GraphError> {
+        self.apply_changes(&[TopologyChange::RemoveToken(mint.to_string())]).map(|_| ())
+    }
+
+    // Add the edges a -> b and b -> a, returns their ids
+    pub fn add_pair(&mut self, a: &str, b: &str, dex_filter: DexFilter) -> Result<Vec<usize>, GraphError> {
+        self.apply_changes(&[
+            TopologyChange::AddEdge { input_mint: a.to_string(), output_mint: b.to_string(), dex_filter: dex_filter.clone() },
+            TopologyChange::AddEdge { input_mint: b.to_string(), output_mint: a.to_string(), dex_filter },
+        ])
+    }
+
+    pub fn add_edge(&mut self, input_mint: &str, output_mint: &str, dex_filter: DexFilter) -> Result<usize, GraphError> {
+        let new_edges = self.apply_changes(&[TopologyChange::AddEdge {
+            input_mint: input_mint.to_string(),
+            output_mint: output_mint.to_string(),
+            dex_filter,
+        }])?;
+        Ok(new_edges[0])
+    }
+
+    pub fn remove_edge(&mut self, input_mint: &str, output_mint: &str, dex_filter: DexFilter) -> Result<(), GraphError> {
+        self.apply_changes(&[TopologyChange::RemoveEdge {
+            input_mint: input_mint.to_string(),
+            output_mint: output_mint.to_string(),
+            dex_filter,
+        }]).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use tokio::sync::Semaphore;
+    use super::*;
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::dynamic_graph::EdgeAttribute;
+    use crate::jupiter::quote::QuoteResponse;
+
+    // S <-> A <-> B <-> S and S <-> C, plus an S -> B edge only quoted on Raydium
+    // Every edge is quoted, at an out amount of 1000 + its id
+    fn graph() -> DynamicGraph {
+        let static_graph = GraphBuilder::new()
+            .test_nodes(&[("S", Some(6)), ("A", Some(6)), ("B", Some(6)), ("C", Some(6))])
+            .mint_pair("S", "A")
+            .mint_pair("A", "B")
+            .mint_pair("B", "S")
+            .mint_pair("S", "C")
+            .mint_one_way_via("S", "B", DexFilter::only(&["Raydium"]))
+            .build()
+            .unwrap();
+        let start_node = static_graph.node_id("S").unwrap();
+        let graph = DynamicGraph::new(Arc::new(static_graph), start_node, 1000);
+        let topology = graph.topology();
+        let updates = (0..topology.n_edge()).map(|edge_idx| (edge_idx, EdgeAttribute {
+            quote_response: Some(QuoteResponse::for_test(topology.input_mint(edge_idx), topology.output_mint(edge_idx),
+                                                         1000, 1000 + edge_idx as u64)),
+            last_updated: Instant::now(),
+            ..EdgeAttribute::new()
+        })).collect();
+        graph.attrs.set_many(&topology, updates);
+        graph
+    }
+
+    fn out_amount(graph: &DynamicGraph, from: &str, to: &str, dex_filter: &DexFilter) -> Option<String> {
+        let table = graph.attrs.load();
+        let edge_idx = table.topology.find_edge(from, to, dex_filter)?;
+        table.get(edge_idx).quote_response.as_ref().map(|rsp| rsp.out_amount.clone())
+    }
+
+    #[test]
+    fn removing_a_token_renumbers_the_edges_and_keeps_their_quotes() {
+        let mut graph = graph();
+        let before = graph.topology();
+        let raydium = DexFilter::only(&["Raydium"]);
+        let kept = [("S", "B", DexFilter::default()), ("B", "S", DexFilter::default()), ("S", "C", DexFilter::default()),
+                    ("C", "S", DexFilter::default()), ("S", "B", raydium.clone())];
+        let quotes: Vec<Option<String>> = kept.iter().map(|(from, to, filter)| out_amount(&graph, from, to, filter)).collect();
+
+        graph.remove_token("A").unwrap();
+
+        let after = graph.topology();
+        assert_eq!(graph.version(), 1);
+        assert_eq!((after.n_node(), after.n_edge()), (3, 5));
+        assert_eq!(after.node(graph.start_node()).mint, "S");
+        // C is renumbered, and the edges keep the quote of the edge with the same mints and DEX filter
+        assert_ne!(after.node_id("C"), before.node_id("C"));
+        for ((from, to, filter), quote) in kept.iter().zip(&quotes) {
+            assert_eq!(&out_amount(&graph, from, to, filter), quote);
+        }
+        // the parallel S -> B edges stay two edges with their own quote
+        assert_ne!(out_amount(&graph, "S", "B", &raydium), out_amount(&graph, "S", "B", &DexFilter::default()));
+    }
+
+    #[test]
+    fn a_token_added_again_gets_fresh_attributes() {
+        let mut graph = graph();
+        let node = graph.topology().node_by_mint("A").unwrap().clone();
+        graph.remove_token("A").unwrap();
+        graph.add_token(node).unwrap();
+        let new_edges = graph.add_pair("S", "A", DexFilter::default()).unwrap();
+
+        let topology = graph.topology();
+        assert_eq!(graph.version(), 3);
+        assert_eq!(new_edges, [topology.find_edge("S", "A", &DexFilter::default()).unwrap(),
+                               topology.find_edge("A", "S", &DexFilter::default()).unwrap()]);
+        let table = graph.attrs.load();
+        assert!(new_edges.iter().all(|&edge_idx| table.get(edge_idx).quote_response.is_none()));
+        // the A <-> B edges were removed with A and aren't back
+        assert_eq!(topology.find_edge("A", "B", &DexFilter::default()), None);
+        assert!(out_amount(&graph, "S", "C", &DexFilter::default()).is_some());
+    }
+
+    #[test]
+    fn a_parallel_edge_is_a_new_edge_and_the_same_one_is_rejected() {
+        let mut graph = graph();
+        let orca = DexFilter::only(&["Orca"]);
+        let edge_idx = graph.add_edge("S", "C", orca.clone()).unwrap();
+        assert_eq!(graph.topology().find_edge("S", "C", &orca), Some(edge_idx));
+        assert!(out_amount(&graph, "S", "C", &orca).is_none());
+        assert!(out_amount(&graph, "S", "C", &DexFilter::default()).is_some());
+
+        // all or none, the valid first change isn't applied either
+        let result = graph.apply_changes(&[
+            TopologyChange::RemoveToken("C".to_string()),
+            TopologyChange::AddEdge { input_mint: "S".to_string(), output_mint: "B".to_string(), dex_filter: DexFilter::default() },
+        ]);
+        assert!(result.is_err());
+        assert_eq!(graph.version(), 1);
+        assert!(graph.topology().node_id("C").is_some());
+    }
+
+    #[tokio::test]
+    async fn a_change_applied_during_a_round_gets_the_quotes_of_the_round() {
+        let mut graph = graph();
+        // quoted long ago, so the round quotes every edge
+        let table = graph.attrs.load();
+        let updates = (0..table.len()).map(|edge_idx| (edge_idx, EdgeAttribute::new())).collect();
+        graph.attrs.set_many(&table.topology, updates);
+
+        let gate = Arc::new(Semaphore::new(0));
+        let update_fn = {
+            let gate = gate.clone();
+            move |params| {
+                let gate = gate.clone();
+                async move {
+                    gate.acquire().await.unwrap().forget();
+                    Ok(QuoteResponse::for_params(&params, 7))
+                }
+            }
+        };
+        let round = tokio::spawn(graph.update_edge_attr(0, 2, update_fn));
+        graph.remove_token("A").unwrap();
+        gate.add_permits(table.len());
+        let results = round.await.unwrap();
+        assert_eq!(results.len(), table.len());
+
+        // the quotes of the edges of A are dropped, the others land on the renumbered edges
+        let current = graph.attrs.load();
+        assert_eq!(current.len(), 5);
+        for edge_idx in 0..current.len() {
+            let quote = current.get(edge_idx).quote_response.as_ref().unwrap();
+            assert_eq!(quote.out_amount, "7");
+            assert_eq!(quote.input_mint, current.topology.input_mint(edge_idx));
+            assert_eq!(quote.output_mint, current.topology.output_mint(edge_idx));
+        }
+    }
+}
@@ -0,0 +1,239 @@
+// Save the state of a DynamicGraph to a file and warm-start from it after a restart
+// The topology is saved in the format of topology_file.rs, with one one-way edge per edge of the graph in id order,
+// so the loaded graph has the same edge ids. attrs[i] is the cached quote of edge i with its wall-clock update time:
+// an Instant means nothing in another process, so last_updated is rebuilt from updated_at when loading.
+// A quote older than max_age when loading is dropped, and its edge is quoted first like a new edge.
+// The prices of the tokens are saved as their reference_price, so the quote amounts are right from the first round.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::graph::dynamic_graph::{DynamicGraph, EdgeAttribute};
+use crate::graph::quote_ladder::QuoteLadder;
+use crate::graph::topology_file::{EdgeEntry, TokenEntry, TopologyError, TopologyFile};
+use crate::jupiter::quote::QuoteResponse;
+use crate::search::opportunity::unix_millis;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphSnapshot {
+    pub saved_at_ms: u64, // milliseconds since the unix epoch
+    pub topology: TopologyFile,
+    pub attrs: Vec<PersistedAttribute>, // attrs[i] is the attribute of edge i
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PersistedAttribute {
+    pub quote_response: Option<QuoteResponse>,
+    pub updated_at_ms: u64, // milliseconds since the unix epoch
+    pub context_slot: Option<i64>, // the slot of the quote, kept next to the quote for inspection
+    #[serde(default)]
+    pub ladder: Option<QuoteLadder>,
+}
+
+#[derive(Debug)]
+pub enum PersistError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    Topology(TopologyError),
+    AttrCountMismatch { n_edge: usize, n_attr: usize },
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistError::Io(e) => write!(f, "Can't access the snapshot file: {}", e),
+            PersistError::Parse(e) => write!(f, "Can't parse the snapshot file: {}", e),
+            PersistError::Topology(e) => write!(f, "Invalid snapshot topology: {}", e),
+            PersistError::AttrCountMismatch { n_edge, n_attr } => write!(f, "The snapshot has {} edges but {} attributes", n_edge, n_attr),
+        }
+    }
+}
+
+impl std::error::Error for PersistError {}
+
+impl GraphSnapshot {
+    pub fn capture(graph: &DynamicGraph) -> Self {
+        // one table, so the attributes are those of the edges of its topology
+        let table = graph.attrs.load();
+        let topology = &table.topology;
+        let start_mint = &topology.node(table.start_node).mint;
+        let tokens = topology.nodes[1..].iter().map(|node| TokenEntry {
+            symbol: node.symbol.clone(),
+            mint: node.mint.clone(),
+            decimals: node.decimals,
+            // the start token always gets its price, which says the unit of the others
+            reference_price: if &node.mint == start_mint {
+                Some(graph.start_price(&table))
+            } else {
+                graph.reference_prices.get(&node.mint).copied()
+            },
+        }).collect();
+        let edges = (0..topology.n_edge()).map(|edge_idx| EdgeEntry {
+            from: topology.input_mint(edge_idx).to_string(),
+            to: topology.output_mint(edge_idx).to_string(),
+            bidirectional: false,
+            dex_filter: topology.edge_info[edge_idx].dex_filter.clone(),
+        }).collect();
+        let attrs = table.attrs.iter().map(|attr| PersistedAttribute {
+            context_slot: attr.quote_response.as_ref().and_then(|rsp| rsp.context_slot),
+            quote_response: attr.quote_response.clone(),
+            updated_at_ms: unix_millis(attr.updated_at),
+            ladder: attr.ladder.clone(),
+        }).collect();
+        Self {
+            saved_at_ms: unix_millis(SystemTime::now()),
+            topology: TopologyFile {
+                tokens,
+                edges,
+                start_token: Some(start_mint.clone()),
+                start_amount: Some(table.start_amount),
+            },
+            attrs,
+        }
+    }
+
+    // Write to a temporary file first, so a crash while saving doesn't destroy the previous snapshot
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PersistError> {
+        let path = path.as_ref();
+        let content = serde_json::to_string(self).map_err(PersistError::Parse)?;
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, content).map_err(PersistError::Io)?;
+        std::fs::rename(&tmp_path, path).map_err(PersistError::Io)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PersistError> {
+        let content = std::fs::read_to_string(path).map_err(PersistError::Io)?;
+        serde_json::from_str(&content).map_err(PersistError::Parse)
+    }
+
+    // The quotes older than max_age are dropped
+    pub fn into_dynamic_graph(self, max_age: Duration) -> Result<DynamicGraph, PersistError> {
+        let graph = self.topology.build().map_err(PersistError::Topology)?.into_dynamic_graph();
+        let n_edge = graph.topology().n_edge();
+        if n_edge != self.attrs.len() {
+            return Err(PersistError::AttrCountMismatch { n_edge, n_attr: self.attrs.len() });
+        }
+        let now = Instant::now();
+        let wall_now = SystemTime::now();
+        let attrs = self.attrs.into_iter().map(|persisted| {
+            let updated_at = UNIX_EPOCH + Duration::from_millis(persisted.updated_at_ms);
+            let age = wall_now.duration_since(updated_at).unwrap_or_default();
+            let mut attr = EdgeAttribute::new();
+            if persisted.quote_response.is_some() && age <= max_age
+                && let Some(last_updated) = now.checked_sub(age) {
+                attr.quote_response = persisted.quote_response;
+                attr.last_updated = last_updated;
+                attr.updated_at = updated_at;
+                attr.ladder = persisted.ladder;
+            }
+            Arc::new(attr)
+        }).collect();
+        graph.attrs.set_all(attrs);
+        Ok(graph)
+    }
+}
+
+pub fn save_snapshot<P: AsRef<Path>>(graph: &DynamicGraph, path: P) -> Result<(), PersistError> {
+    GraphSnapshot::capture(graph).save(path)
+}
+
+pub fn load_snapshot<P: AsRef<Path>>(path: P, max_age: Duration) -> Result<DynamicGraph, PersistError> {
+    GraphSnapshot::load(path)?.into_dynamic_graph(max_age)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::quote_ladder::LadderRung;
+    use crate::graph::static_graph::{DexFilter, GraphError, NodeInfo};
+
+    // WSOL <-> USDC, a Raydium-only WSOL -> USDC edge and a one-way USDC -> BONK
+    fn graph() -> DynamicGraph {
+        let static_graph = GraphBuilder::new()
+            .test_nodes(&[("WSOL", Some(9)), ("USDC", Some(6)), ("BONK", None)])
+            .mint_pair("WSOL", "USDC")
+            .mint_one_way_via("WSOL", "USDC", DexFilter::only(&["Raydium"]))
+            .mint_one_way("USDC", "BONK")
+            .build()
+            .unwrap();
+        let start_node = static_graph.node_id("WSOL").unwrap();
+        let mut graph = DynamicGraph::new(Arc::new(static_graph), start_node, 1_000_000_000);
+        graph.reference_prices.insert("USDC".to_string(), 0.005);
+        graph
+    }
+
+    fn quoted(input_mint: &str, output_mint: &str, age: Duration) -> EdgeAttribute {
+        EdgeAttribute {
+            quote_response: Some(QuoteResponse::for_test(input_mint, output_mint, 1_000, 2_000)),
+            last_updated: Instant::now() - age,
+            updated_at: SystemTime::now() - age,
+            ladder: Some(QuoteLadder { rungs: vec![LadderRung { in_amount: 1_000, out_amount: 2_000 }] }),
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_the_edge_ids_quotes_and_prices() {
+        let graph = graph();
+        let topology = graph.topology();
+        let fresh = topology.find_edge("WSOL", "USDC", &DexFilter::only(&["Raydium"])).unwrap();
+        let old = topology.find_edge("USDC", "BONK", &DexFilter::default()).unwrap();
+        graph.attrs.set_many(&topology, vec![
+            (fresh, quoted("WSOL", "USDC", Duration::from_secs(60))),
+            (old, quoted("USDC", "BONK", Duration::from_secs(3600))),
+        ]);
+
+        let content = serde_json::to_string(&GraphSnapshot::capture(&graph)).unwrap();
+        let snapshot: GraphSnapshot = serde_json::from_str(&content).unwrap();
+        let loaded = snapshot.into_dynamic_graph(Duration::from_secs(600)).unwrap();
+
+        let loaded_topology = loaded.topology();
+        assert_eq!(loaded_topology.n_edge(), topology.n_edge());
+        for edge_idx in 0..topology.n_edge() {
+            assert_eq!(loaded_topology.input_mint(edge_idx), topology.input_mint(edge_idx));
+            assert_eq!(loaded_topology.output_mint(edge_idx), topology.output_mint(edge_idx));
+            assert_eq!(loaded_topology.edge_info[edge_idx].dex_filter, topology.edge_info[edge_idx].dex_filter);
+        }
+        assert_eq!(loaded.start_node(), graph.start_node());
+        assert_eq!(loaded.start_amount(), graph.start_amount());
+        assert_eq!(loaded_topology.node_by_mint("BONK").unwrap().decimals, None);
+        assert_eq!(loaded.reference_prices.get("USDC"), Some(&0.005));
+        assert_eq!(loaded.reference_prices.get("WSOL"), Some(&1.0));
+
+        let table = loaded.attrs.load();
+        let fresh_attr = table.get(fresh);
+        assert_eq!(fresh_attr.quote_response.as_ref().unwrap().out_amount, "2000");
+        assert_eq!(fresh_attr.ladder.as_ref().unwrap().rungs, [LadderRung { in_amount: 1_000, out_amount: 2_000 }]);
+        let age = fresh_attr.last_updated.elapsed();
+        assert!(age >= Duration::from_secs(59) && age < Duration::from_secs(120));
+        // older than max_age, quoted again first
+        assert!(table.get(old).quote_response.is_none());
+        assert!(table.get(old).ladder.is_none());
+    }
+
+    #[test]
+    fn tokens_added_at_runtime_keep_the_snapshot_loadable() {
+        let mut graph = graph();
+        // the symbol names the token in the snapshot, a second USDC would make it invalid
+        let usdc2 = NodeInfo { mint: "USDC2".to_string(), symbol: "USDC".to_string(), decimals: Some(6) };
+        assert_eq!(graph.add_token(usdc2).err(), Some(GraphError::DuplicateSymbol("USDC".to_string())));
+        let jup = NodeInfo { mint: "JUP".to_string(), symbol: "JUP".to_string(), decimals: Some(6) };
+        graph.add_token(jup).unwrap();
+        graph.add_pair("USDC", "JUP", DexFilter::default()).unwrap();
+
+        let loaded = GraphSnapshot::capture(&graph).into_dynamic_graph(Duration::from_secs(600)).unwrap();
+        assert_eq!(loaded.topology().n_node(), 4);
+        assert_eq!(loaded.topology().find_edge("JUP", "USDC", &DexFilter::default()),
+                   graph.topology().find_edge("JUP", "USDC", &DexFilter::default()));
+    }
+
+    #[test]
+    fn rejects_a_snapshot_with_missing_attributes() {
+        let mut snapshot = GraphSnapshot::capture(&graph());
+        snapshot.attrs.pop();
+        assert!(matches!(snapshot.into_dynamic_graph(Duration::from_secs(600)),
+                         Err(PersistError::AttrCountMismatch { n_edge: 4, n_attr: 3 })));
+    }
+}
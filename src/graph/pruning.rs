@@ -0,0 +1,214 @@
+// Find the edges that can't be on any cycle through the start node with at most max_path_len edges
+// Such edges cost quotes in every update round but never show up in an opportunity.
+// An edge u -> v is kept if the start node, u and v are in the same strongly connected component and
+// hops(start -> u) + 1 + hops(v -> start) <= max_path_len, with hops the BFS distances.
+// The shortest closed walk through the edge may repeat a node, so a kept edge isn't always on a simple cycle,
+// but a pruned edge is never on one: pruning never loses an opportunity.
+
+use std::collections::VecDeque;
+use serde::Serialize;
+use crate::graph::static_graph::StaticGraph;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StartCyclePruning {
+    pub start_node: usize,
+    pub max_path_len: usize,
+    pub component: Vec<usize>, // component[i] is the strongly connected component of node i
+    pub live_edges: Vec<bool>, // live_edges[i] = false if edge i is pruned
+    pub dead_edges: Vec<usize>,
+    pub dead_nodes: Vec<usize>, // the nodes without any live edge, other than the start node
+}
+
+impl StartCyclePruning {
+    pub fn analyze(graph: &StaticGraph, start_node: usize, max_path_len: usize) -> Self {
+        let component = graph.strongly_connected_components();
+        let from_start = hops(graph, start_node, false);
+        let to_start = hops(graph, start_node, true);
+
+        let mut live_edges = vec![false; graph.n_edge()];
+        let mut dead_edges = vec![];
+        let mut live_nodes = vec![false; graph.head.len()];
+        live_nodes[start_node] = true;
+        for (edge_idx, live) in live_edges.iter_mut().enumerate() {
+            let (from, to, _) = graph.edge(edge_idx);
+            let in_component = component[from] == component[start_node] && component[to] == component[start_node];
+            *live = in_component && match (from_start[from], to_start[to]) {
+                (Some(before), Some(after)) => before + 1 + after <= max_path_len,
+                _ => false,
+            };
+            if *live {
+                live_nodes[from] = true;
+                live_nodes[to] = true;
+            } else {
+                dead_edges.push(edge_idx);
+            }
+        }
+        let dead_nodes = (1..=graph.n_node()).filter(|&node| !live_nodes[node]).collect();
+        Self {
+            start_node,
+            max_path_len,
+            component,
+            live_edges,
+            dead_edges,
+            dead_nodes,
+        }
+    }
+
+    pub fn n_live_edges(&self) -> usize {
+        self.live_edges.len() - self.dead_edges.len()
+    }
+
+    // The symbols of the tokens no cycle through the start node can visit
+    pub fn dead_tokens<'a>(&self, graph: &'a StaticGraph) -> Vec<&'a str> {
+        self.dead_nodes.iter().map(|&node| graph.node(node).symbol.as_str()).collect()
+    }
+}
+
+impl StaticGraph {
+    // component[i] is the id of the strongly connected component of node i (Tarjan's algorithm, without recursion)
+    pub fn strongly_connected_components(&self) -> Vec<usize> {
+        let n_slot = self.head.len();
+        let csr = self.frozen();
+        let mut index = vec![usize::MAX; n_slot];
+        let mut lowlink = vec![0; n_slot];
+        let mut on_stack = vec![false; n_slot];
+        let mut stack = vec![];
+        let mut component = vec![usize::MAX; n_slot];
+        let mut next_index = 0;
+        let mut n_component = 0;
+        for root in 0..n_slot {
+            if index[root] != usize::MAX {
+                continue;
+            }
+            // (node, position of the next out edge to visit)
+            let mut calls = vec![(root, 0)];
+            index[root] = next_index;
+            lowlink[root] = next_index;
+            next_index += 1;
+            stack.push(root);
+            on_stack[root] = true;
+            while let Some(frame) = calls.last_mut() {
+                let node = frame.0;
+                let targets = csr.out_targets(node);
+                if frame.1 < targets.len() {
+                    let to = targets[frame.1];
+                    frame.1 += 1;
+                    if index[to] == usize::MAX {
+                        index[to] = next_index;
+                        lowlink[to] = next_index;
+                        next_index += 1;
+                        stack.push(to);
+                        on_stack[to] = true;
+                        calls.push((to, 0));
+                    } else if on_stack[to] {
+                        lowlink[node] = lowlink[node].min(index[to]);
+                    }
+                    continue;
+                }
+                calls.pop();
+                if let Some(&(parent, _)) = calls.last() {
+                    lowlink[parent] = lowlink[parent].min(lowlink[node]);
+                }
+                if lowlink[node] == index[node] {
+                    while let Some(member) = stack.pop() {
+                        on_stack[member] = false;
+                        component[member] = n_component;
+                        if member == node {
+                            break;
+                        }
+                    }
+                    n_component += 1;
+                }
+            }
+        }
+        component
+    }
+}
+
+// hops[i] is the number of edges of the shortest path from `node` to i, or from i to `node` if reverse
+fn hops(graph: &StaticGraph, node: usize, reverse: bool) -> Vec<Option<usize>> {
+    let csr = graph.frozen();
+    let mut hops = vec![None; graph.head.len()];
+    hops[node] = Some(0);
+    let mut queue = VecDeque::from([node]);
+    while let Some(current) = queue.pop_front() {
+        let next_hops = hops[current].map(|h| h + 1);
+        let neighbours: Vec<usize> = if reverse {
+            csr.in_edges(current).map(|(_, source)| source).collect()
+        } else {
+            csr.out_targets(current).to_vec()
+        };
+        for neighbour in neighbours {
+            if hops[neighbour].is_none() {
+                hops[neighbour] = next_hops;
+                queue.push_back(neighbour);
+            }
+        }
+    }
+    hops
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::cycle_index::CycleIndex;
+    use crate::graph::dynamic_graph::DynamicGraph;
+    use crate::graph::static_graph::DexFilter;
+
+    // S <-> A <-> B <-> C <-> D, and a one-way S -> E that never comes back
+    fn chain() -> StaticGraph {
+        GraphBuilder::new()
+            .test_nodes(&[("S", Some(6)), ("A", Some(6)), ("B", Some(6)), ("C", Some(6)), ("D", Some(6)), ("E", Some(6))])
+            .mint_pair("S", "A")
+            .mint_pair("A", "B")
+            .mint_pair("B", "C")
+            .mint_pair("C", "D")
+            .mint_one_way("S", "E")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn keeps_the_edges_within_reach_of_the_start_node() {
+        let graph = chain();
+        let edge = |from, to| graph.find_edge(from, to, &DexFilter::default()).unwrap();
+        let pruning = StartCyclePruning::analyze(&graph, graph.node_id("S").unwrap(), 4);
+        for (from, to) in [("S", "A"), ("A", "S"), ("A", "B"), ("B", "A")] {
+            assert!(pruning.live_edges[edge(from, to)], "{} -> {} should be live", from, to);
+        }
+        // S -> A -> B -> C -> B -> A -> S is 6 edges long
+        for (from, to) in [("B", "C"), ("C", "B"), ("C", "D"), ("D", "C"), ("S", "E")] {
+            assert!(!pruning.live_edges[edge(from, to)], "{} -> {} should be pruned", from, to);
+        }
+        let mut dead_tokens = pruning.dead_tokens(&graph);
+        dead_tokens.sort();
+        assert_eq!(dead_tokens, ["C", "D", "E"]);
+        assert_eq!(pruning.n_live_edges(), 4);
+    }
+
+    #[test]
+    fn never_prunes_an_edge_of_a_cycle_through_the_start_node() {
+        let graph = chain();
+        let start = graph.node_id("S").unwrap();
+        for max_path_len in 2..=8 {
+            let pruning = StartCyclePruning::analyze(&graph, start, max_path_len);
+            let index = CycleIndex::through_node(&graph, start, max_path_len);
+            for cycle in &index.cycles {
+                assert!(cycle.edges.iter().all(|&edge_idx| pruning.live_edges[edge_idx]));
+            }
+        }
+    }
+
+    #[test]
+    fn prune_stops_the_updates_of_the_pruned_edges() {
+        let graph = Arc::new(chain());
+        let edge = |from, to| graph.find_edge(from, to, &DexFilter::default()).unwrap();
+        let mut dynamic_graph = DynamicGraph::new(graph.clone(), graph.node_id("S").unwrap(), 1_000_000);
+        assert!(dynamic_graph.is_live(edge("S", "E")));
+        dynamic_graph.prune(4);
+        assert!(!dynamic_graph.is_live(edge("S", "E")));
+        assert!(dynamic_graph.is_live(edge("S", "A")));
+    }
+}
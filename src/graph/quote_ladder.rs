@@ -0,0 +1,125 @@
+// Quotes of one edge at several input sizes, to estimate how the rate degrades with the size
+// A single quote only gives the rate at one amount, scaling it linearly ignores the price impact.
+// With a ladder, out_amount is interpolated linearly between the two rungs around the input, with (0, 0)
+// as the implicit first rung. Above the largest rung the marginal rate of the last two rungs is extended,
+// which is optimistic if the curve keeps bending, so the largest rung should cover the sizes that matter.
+
+use serde::{Deserialize, Serialize};
+use crate::jupiter::quote::QuoteResponse;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LadderRung {
+    pub in_amount: u64,
+    pub out_amount: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuoteLadder {
+    pub rungs: Vec<LadderRung>, // sorted by in_amount, without duplicate or zero in amounts
+}
+
+impl QuoteLadder {
+    // The quotes whose amounts can't be parsed are ignored, None if no quote is left
+    pub fn from_quotes(quotes: &[QuoteResponse]) -> Option<Self> {
+        let mut rungs: Vec<LadderRung> = quotes.iter()
+            .filter_map(|quote_rsp| Some(LadderRung {
+                in_amount: quote_rsp.in_amount.parse().ok()?,
+                out_amount: quote_rsp.out_amount.parse().ok()?,
+            }))
+            .filter(|rung| rung.in_amount > 0)
+            .collect();
+        rungs.sort_by_key(|rung| rung.in_amount);
+        rungs.dedup_by_key(|rung| rung.in_amount);
+        if rungs.is_empty() {
+            return None;
+        }
+        Some(Self { rungs })
+    }
+
+    // The estimated out amount for in_amount
+    pub fn estimate_out(&self, in_amount: u64) -> u64 {
+        let pos = self.rungs.partition_point(|rung| rung.in_amount < in_amount);
+        if let Some(rung) = self.rungs.get(pos) {
+            if rung.in_amount == in_amount {
+                return rung.out_amount;
+            }
+            let (prev_in, prev_out) = if pos == 0 {
+                (0, 0)
+            } else {
+                (self.rungs[pos - 1].in_amount, self.rungs[pos - 1].out_amount)
+            };
+            return interpolate((prev_in, prev_out), (rung.in_amount, rung.out_amount), in_amount);
+        }
+        // above the largest rung
+        let last = &self.rungs[self.rungs.len() - 1];
+        let (prev_in, prev_out) = if self.rungs.len() >= 2 {
+            let prev = &self.rungs[self.rungs.len() - 2];
+            (prev.in_amount, prev.out_amount)
+        } else {
+            (0, 0)
+        };
+        interpolate((prev_in, prev_out), (last.in_amount, last.out_amount), in_amount)
+    }
+}
+
+// The out amount at x on the line through a and b (a.0 < b.0), never negative
+fn interpolate(a: (u64, u64), b: (u64, u64), x: u64) -> u64 {
+    let dx = (b.0 - a.0) as i128;
+    let dy = b.1 as i128 - a.1 as i128;
+    let y = match dy.checked_mul(x as i128 - a.0 as i128) {
+        Some(product) => a.1 as i128 + product / dx,
+        None => (a.1 as f64 + dy as f64 * (x as f64 - a.0 as f64) / dx as f64) as i128,
+    };
+    y.clamp(0, u64::MAX as i128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ladder() -> QuoteLadder {
+        QuoteLadder {
+            rungs: vec![
+                LadderRung { in_amount: 100, out_amount: 200 },
+                LadderRung { in_amount: 1000, out_amount: 1800 },
+                LadderRung { in_amount: 10000, out_amount: 15000 },
+            ],
+        }
+    }
+
+    #[test]
+    fn returns_the_rungs_and_interpolates_between_them() {
+        let ladder = ladder();
+        assert_eq!(ladder.estimate_out(100), 200);
+        assert_eq!(ladder.estimate_out(10000), 15000);
+        // (0, 0) is the first rung
+        assert_eq!(ladder.estimate_out(50), 100);
+        assert_eq!(ladder.estimate_out(0), 0);
+        assert_eq!(ladder.estimate_out(550), 1000);
+    }
+
+    #[test]
+    fn extends_the_last_marginal_rate_above_the_largest_rung() {
+        let ladder = ladder();
+        assert_eq!(ladder.estimate_out(20000), 15000 + 13200 * 10000 / 9000);
+        // the estimate is clamped to u64 instead of wrapping around
+        assert!(ladder.estimate_out(u64::MAX) > 0);
+
+        let single = QuoteLadder { rungs: vec![LadderRung { in_amount: 100, out_amount: 200 }] };
+        assert_eq!(single.estimate_out(300), 600);
+    }
+
+    #[test]
+    fn from_quotes_sorts_and_drops_duplicate_and_zero_amounts() {
+        let quotes = [
+            QuoteResponse::for_test("A", "B", 1000, 1800),
+            QuoteResponse::for_test("A", "B", 0, 5),
+            QuoteResponse::for_test("A", "B", 100, 200),
+            QuoteResponse::for_test("A", "B", 1000, 1700),
+        ];
+        let ladder = QuoteLadder::from_quotes(&quotes).unwrap();
+        let in_amounts: Vec<u64> = ladder.rungs.iter().map(|rung| rung.in_amount).collect();
+        assert_eq!(in_amounts, [100, 1000]);
+        assert!(QuoteLadder::from_quotes(&[QuoteResponse::for_test("A", "B", 0, 5)]).is_none());
+    }
+}
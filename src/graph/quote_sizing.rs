@@ -0,0 +1,155 @@
+// The amount each edge is quoted at, the same notional value in the input token of every edge
+// start_amount of the start token is the notional value. An edge out of token X is quoted at that value divided
+// by the price of X, in the smallest unit of X, so a USDC edge and a WETH edge are quoted at the same size as a
+// start edge. Prices are in any common unit (e.g. USD, or the start token, whose price defaults to 1): they come
+// from the reference_price of the topology file and are derived again from the cached quotes after every update
+// round, so the amounts follow the market. A token without a price yet is assumed to be worth one start token,
+// which still accounts for its decimals until its first quote prices it. A token with unknown decimals (a mint
+// found by discovery.rs) is priced per smallest unit, and its edges are not quoted until it has a price.
+
+use std::collections::VecDeque;
+use crate::graph::attr_table::AttrTable;
+use crate::graph::dynamic_graph::DynamicGraph;
+use crate::graph::static_graph::NodeInfo;
+
+impl DynamicGraph {
+    // The price of the start token, the unit every other price is relative to
+    pub(crate) fn start_price(&self, table: &AttrTable) -> f64 {
+        let start_mint = &table.topology.node(table.start_node).mint;
+        self.reference_prices.get(start_mint).copied().unwrap_or(1.0)
+    }
+
+    // The raw amount edge_idx is quoted at, start_amount for the edges out of the start node
+    // None if the decimals of the input token are unknown and no quote gave it a price yet
+    pub fn quote_amount(&self, edge_idx: usize) -> Option<u64> {
+        self.quote_amount_in(&self.attrs.load(), edge_idx)
+    }
+
+    // Same as quote_amount, for an edge of the topology of `table`
+    pub(crate) fn quote_amount_in(&self, table: &AttrTable, edge_idx: usize) -> Option<u64> {
+        let topology = &table.topology;
+        let input_node = topology.edge_info[edge_idx].input_node;
+        if input_node == table.start_node {
+            return Some(table.start_amount);
+        }
+        let token = topology.node(input_node);
+        let start_price = self.start_price(table);
+        let (price, unit) = match (self.reference_prices.get(&token.mint), token.unit()) {
+            (Some(&price), unit) => (price, unit.unwrap_or(1.0)),
+            (None, Some(unit)) => (start_price, unit),
+            (None, None) => return None,
+        };
+        let notional = table.start_amount as f64 / smallest_units(topology.node(table.start_node)) * start_price;
+        let amount = notional / price * unit;
+        // the cast saturates, and a quote of 0 is rejected by Jupiter
+        Some((amount.round() as u64).max(1))
+    }
+
+    // Derive the price of the tokens from the cached quotes of the live edges, walking out from the start node:
+    // the price of a token comes from a quote into it from the closest token with a price, returns the number
+    // of tokens priced. The tokens no quote reaches keep their previous price
+    pub fn reprice(&mut self) -> usize {
+        let table = self.attrs.load();
+        let graph = &table.topology;
+        let mut prices: Vec<Option<f64>> = vec![None; graph.nodes.len()];
+        prices[table.start_node] = Some(self.start_price(&table));
+        let mut queue = VecDeque::from([table.start_node]);
+        while let Some(node) = queue.pop_front() {
+            let Some(price) = prices[node] else { continue };
+            let in_unit = smallest_units(graph.node(node));
+            for &edge_idx in graph.frozen().out_edge_ids(node) {
+                let output_node = graph.edge_info[edge_idx].output_node;
+                if prices[output_node].is_some() || !table.is_live(edge_idx) {
+                    continue;
+                }
+                let Some(quote_rsp) = &table.get(edge_idx).quote_response else { continue };
+                let (Ok(in_amount), Ok(out_amount)) = (quote_rsp.in_amount.parse::<u64>(), quote_rsp.out_amount.parse::<u64>()) else {
+                    continue;
+                };
+                if in_amount == 0 || out_amount == 0 {
+                    continue;
+                }
+                let in_tokens = in_amount as f64 / in_unit;
+                let out_tokens = out_amount as f64 / smallest_units(graph.node(output_node));
+                prices[output_node] = Some(price * in_tokens / out_tokens);
+                queue.push_back(output_node);
+            }
+        }
+        let mut n_priced = 0;
+        for (node, price) in prices.into_iter().enumerate().skip(1) {
+            if let Some(price) = price && node != table.start_node {
+                self.reference_prices.insert(graph.node(node).mint.clone(), price);
+                n_priced += 1;
+            }
+        }
+        n_priced
+    }
+}
+
+// The smallest units per unit a price is given for, one if the decimals are unknown
+fn smallest_units(node: &NodeInfo) -> f64 {
+    node.unit().unwrap_or(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::dynamic_graph::{DynamicGraph, EdgeAttribute};
+    use crate::graph::static_graph::DexFilter;
+    use crate::jupiter::quote::QuoteResponse;
+
+    // WSOL (9 decimals) <-> USDC (6) <-> WETH (8), and USDC <-> XYZ whose decimals are unknown
+    // Before any quote, USDC is worth 1/200 WSOL and WETH has no price
+    fn graph() -> DynamicGraph {
+        let static_graph = GraphBuilder::new()
+            .test_nodes(&[("WSOL", Some(9)), ("USDC", Some(6)), ("WETH", Some(8)), ("XYZ", None)])
+            .mint_pair("WSOL", "USDC")
+            .mint_pair("USDC", "WETH")
+            .mint_pair("USDC", "XYZ")
+            .build()
+            .unwrap();
+        let start_node = static_graph.node_id("WSOL").unwrap();
+        let mut graph = DynamicGraph::new(Arc::new(static_graph), start_node, 1_000_000_000);
+        graph.reference_prices.insert("WSOL".to_string(), 1.0);
+        graph.reference_prices.insert("USDC".to_string(), 0.005);
+        graph
+    }
+
+    fn set_quote(graph: &DynamicGraph, from: &str, to: &str, in_amount: u64, out_amount: u64) {
+        let attr = EdgeAttribute {
+            quote_response: Some(QuoteResponse::for_test(from, to, in_amount, out_amount)),
+            ..EdgeAttribute::new()
+        };
+        let topology = graph.topology();
+        graph.attrs.set_many(&topology, vec![(topology.find_edge(from, to, &DexFilter::default()).unwrap(), attr)]);
+    }
+
+    #[test]
+    fn quotes_every_edge_at_the_value_of_the_start_amount() {
+        let graph = graph();
+        let amount = |from, to| graph.quote_amount(graph.topology().find_edge(from, to, &DexFilter::default()).unwrap());
+        assert_eq!(amount("WSOL", "USDC"), Some(1_000_000_000));
+        assert_eq!(amount("USDC", "WSOL"), Some(200_000_000));
+        // one whole WETH, worth one start token until a quote prices it
+        assert_eq!(amount("WETH", "USDC"), Some(100_000_000));
+        assert_eq!(amount("XYZ", "USDC"), None);
+    }
+
+    #[test]
+    fn reprice_follows_the_cached_quotes() {
+        let mut graph = graph();
+        set_quote(&graph, "WSOL", "USDC", 1_000_000_000, 150_000_000);
+        set_quote(&graph, "USDC", "WETH", 200_000_000, 6_666_666);
+        set_quote(&graph, "USDC", "XYZ", 150_000_000, 42_000);
+        assert_eq!(graph.reprice(), 3);
+
+        let amount = |from, to| graph.quote_amount(graph.topology().find_edge(from, to, &DexFilter::default()).unwrap());
+        assert_eq!(amount("WSOL", "USDC"), Some(1_000_000_000));
+        assert_eq!(amount("USDC", "WSOL"), Some(150_000_000));
+        assert_eq!(amount("USDC", "WETH"), Some(150_000_000));
+        assert_eq!(amount("WETH", "USDC"), Some(5_000_000));
+        // priced per smallest unit, 42000 of them are worth one start token
+        assert_eq!(amount("XYZ", "USDC"), Some(42_000));
+    }
+}
@@ -1,101 +1,214 @@
-use std::collections::HashMap;
 use std::sync::Arc;
-use crate::graph::{dynamic_graph, static_graph};
-use crate::jupiter::quote::{quote, QuoteParams};
+use crate::graph::builder::GraphBuilder;
+use crate::graph::dynamic_graph;
+use crate::jupiter::quote::quote_jupiter;
 use crate::mints::mints;
+use crate::graph::cycle_index::CycleIndex;
+use crate::graph::discovery::MintDiscovery;
+use crate::graph::mutation::TopologyChange;
+use crate::graph::persist;
+use std::path::PathBuf;
+use tokio::sync::{mpsc, watch};
+use crate::search::cached_search::{search_index, QuoteSnapshot};
+use crate::search::negative_cycle::search_negative_cycles;
+use crate::search::trade_size::optimize_trade_size_estimated;
 
 pub fn create_graph() -> dynamic_graph::DynamicGraph {
-    // When initializing the static graph, we need to know the number of nodes
-    let mut static_graph = static_graph::StaticGraph::new(6);
-
-    // And we also need to assign the node id (from 1 to n_node) to the mint address
-    let mut token2id = HashMap::new();
-    let mut id2token = HashMap::new();
-    token2id.insert(mints::WSOL.mint, 1);
-    id2token.insert(1, mints::WSOL.mint);
-    token2id.insert(mints::USDC.mint, 2);
-    id2token.insert(2, mints::USDC.mint);
-    token2id.insert(mints::USDT.mint, 3);
-    id2token.insert(3, mints::USDT.mint);
-    token2id.insert(mints::WETH.mint, 4);
-    id2token.insert(4, mints::WETH.mint);
-    token2id.insert(mints::FARTCOIN.mint, 5);
-    id2token.insert(5, mints::FARTCOIN.mint);
-    token2id.insert(mints::POPCAT.mint, 6);
-    id2token.insert(6, mints::POPCAT.mint);
-
-    // Add edges to the graph
-    // WSOL <-> USDC
-    static_graph.add_edge(token2id[&mints::WSOL.mint], token2id[&mints::USDC.mint], mints::WSOL.mint.to_string(), mints::USDC.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDC.mint], token2id[&mints::WSOL.mint], mints::USDC.mint.to_string(), mints::WSOL.mint.to_string());
-    // WSOL <-> USDT
-    static_graph.add_edge(token2id[&mints::WSOL.mint], token2id[&mints::USDT.mint], mints::WSOL.mint.to_string(), mints::USDT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDT.mint], token2id[&mints::WSOL.mint], mints::USDT.mint.to_string(), mints::WSOL.mint.to_string());
-    // USDC <-> USDT
-    static_graph.add_edge(token2id[&mints::USDC.mint], token2id[&mints::USDT.mint], mints::USDC.mint.to_string(), mints::USDT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDT.mint], token2id[&mints::USDC.mint], mints::USDT.mint.to_string(), mints::USDC.mint.to_string());
-
-    // SOL <-> WETH, USDC <-> WETH, USDT <-> WETH
-    static_graph.add_edge(token2id[&mints::USDC.mint], token2id[&mints::WETH.mint], mints::USDC.mint.to_string(), mints::WETH.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WETH.mint], token2id[&mints::USDC.mint], mints::WETH.mint.to_string(), mints::USDC.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDT.mint], token2id[&mints::WETH.mint], mints::USDT.mint.to_string(), mints::WETH.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WETH.mint], token2id[&mints::USDT.mint], mints::WETH.mint.to_string(), mints::USDT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WSOL.mint], token2id[&mints::WETH.mint], mints::WSOL.mint.to_string(), mints::WETH.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WETH.mint], token2id[&mints::WSOL.mint], mints::WETH.mint.to_string(), mints::WSOL.mint.to_string());
-
-    // SOL <-> FARTCOIN, USDC <-> FARTCOIN, USDT <-> FARTCOIN
-    static_graph.add_edge(token2id[&mints::USDC.mint], token2id[&mints::FARTCOIN.mint], mints::USDC.mint.to_string(), mints::FARTCOIN.mint.to_string());
-    static_graph.add_edge(token2id[&mints::FARTCOIN.mint], token2id[&mints::USDC.mint], mints::FARTCOIN.mint.to_string(), mints::USDC.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDT.mint], token2id[&mints::FARTCOIN.mint], mints::USDT.mint.to_string(), mints::FARTCOIN.mint.to_string());
-    static_graph.add_edge(token2id[&mints::FARTCOIN.mint], token2id[&mints::USDT.mint], mints::FARTCOIN.mint.to_string(), mints::USDT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WSOL.mint], token2id[&mints::FARTCOIN.mint], mints::WSOL.mint.to_string(), mints::FARTCOIN.mint.to_string());
-    static_graph.add_edge(token2id[&mints::FARTCOIN.mint], token2id[&mints::WSOL.mint], mints::FARTCOIN.mint.to_string(), mints::WSOL.mint.to_string());
-
-    // SOL <-> POPCAT, USDC <-> POPCAT, USDT <-> POPCAT
-    static_graph.add_edge(token2id[&mints::USDC.mint], token2id[&mints::POPCAT.mint], mints::USDC.mint.to_string(), mints::POPCAT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::POPCAT.mint], token2id[&mints::USDC.mint], mints::POPCAT.mint.to_string(), mints::USDC.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDT.mint], token2id[&mints::POPCAT.mint], mints::USDT.mint.to_string(), mints::POPCAT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::POPCAT.mint], token2id[&mints::USDT.mint], mints::POPCAT.mint.to_string(), mints::USDT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WSOL.mint], token2id[&mints::POPCAT.mint], mints::WSOL.mint.to_string(), mints::POPCAT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::POPCAT.mint], token2id[&mints::WSOL.mint], mints::POPCAT.mint.to_string(), mints::WSOL.mint.to_string());
-
-    let dynamic_graph = dynamic_graph::DynamicGraph::new(Arc::from(static_graph), 1, 1000000000);
-
-    dynamic_graph
+    let static_graph = GraphBuilder::new()
+        .tokens(&mints::DEFAULT_TOKENS)
+        .pairs(&mints::DEFAULT_PAIRS)
+        .build()
+        .expect("The default tokens and pairs form a valid graph");
+    let start_node = static_graph.node_id(mints::WSOL.mint).unwrap();
+    dynamic_graph::DynamicGraph::new(Arc::from(static_graph), start_node, 1000000000)
 }
 
 pub async fn schedule_update() {
-    // Create the graph
-    let mut graph = create_graph();
+    schedule_update_graph(create_graph()).await;
+}
+
+// Keep the quotes of every edge of the graph warm, and search the cycles over them after each round
+pub async fn schedule_update_graph(graph: dynamic_graph::DynamicGraph) {
+    schedule_update_graph_with(graph, UpdateOptions::default()).await;
+}
+
+#[derive(Default)]
+pub struct UpdateOptions {
+    pub changes: Option<mpsc::UnboundedReceiver<Vec<TopologyChange>>>, // topology changes, applied as they come, during an update round too
+    pub discovery: Option<MintDiscovery>, // count the mints Jupiter routes through, and propose or add them as nodes
+    pub persist: Option<PersistOptions>,
+    pub negative_cycles: bool, // search with Bellman-Ford instead of enumerating the cycles, for graphs too large for the cycle index
+}
+
+pub struct PersistOptions {
+    pub path: PathBuf,
+    pub interval: std::time::Duration, // the time between two saves, the graph is saved on Ctrl-C too
+}
+
+// Same as schedule_update_graph, with the optional features of UpdateOptions
+pub async fn schedule_update_graph_with(mut graph: dynamic_graph::DynamicGraph, options: UpdateOptions) {
+    let UpdateOptions { mut changes, mut discovery, persist, negative_cycles } = options;
     let interval = std::time::Duration::from_millis(10);
     let min_millis = 50;
     let max_concurrency = 50;
-    let update_fn = |start_amount: u64, input_mint: String, output_mint: String| {
-        async move {
-            let jupiter_url = "http://64.130.36.228:18080";
-            let quote_params = QuoteParams::new(
-                input_mint,
-                output_mint,
-                start_amount,
-            );
-
-            // Use a different approach to make the error Send-compatible
-            match quote(jupiter_url, quote_params).await {
-                Ok(response) => Ok(response),
-                Err(e) => {
-                    // Convert the error to a Send-compatible error
-                    let error_message = format!("Quote error: {}", e);
-                    Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, error_message)) as Box<dyn std::error::Error + Send>)
-                }
-            }
+    let max_path_len = 4;
+    let max_quote_age = std::time::Duration::from_secs(2);
+    // Report the nodes no cycle can use before spending quotes on their edges
+    let report = graph.topology().validate(graph.start_node());
+    if !report.is_ok() {
+        eprintln!("{}", report);
+        if !report.metadata_errors.is_empty() {
+            return;
         }
-    };
+    }
+    let mut cycle_index = follow_topology(&mut graph, negative_cycles, max_path_len);
+    let mut index_version = graph.version();
+    let mut last_save = std::time::Instant::now();
+    // Listen for Ctrl-C from the start, so one pressed during the first round (a full re-quote on a cold start)
+    // still saves the graph. Without persistence, Ctrl-C keeps its default behavior and exits at once
+    let mut shutdown = persist.as_ref().map(|_| {
+        let (sender, receiver) = watch::channel(false);
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                let _ = sender.send(true);
+            }
+        });
+        receiver
+    });
     loop {
-        // Await the future to properly handle it
-        let results = graph.update_edge_attr(min_millis, max_concurrency, update_fn).await;
+        while let Some(change) = changes.as_mut().and_then(|changes| changes.try_recv().ok()) {
+            apply_change(&mut graph, &change);
+        }
+        // Prune before the round, so the edges of a new topology that no cycle can use aren't quoted
+        if graph.version() != index_version {
+            cycle_index = follow_topology(&mut graph, negative_cycles, max_path_len);
+            index_version = graph.version();
+        }
+
+        // The round doesn't borrow the graph, so the changes received while it runs are applied at once
+        // and its remaining quotes land on the new topology
+        let round_start = std::time::Instant::now();
+        let round = graph.update_edge_attr(min_millis, max_concurrency, quote_jupiter);
+        tokio::pin!(round);
+        let results = loop {
+            tokio::select! {
+                results = &mut round => break results,
+                Some(change) = next_change(&mut changes) => apply_change(&mut graph, &change),
+            }
+        };
 
         let success_count = results.iter().filter(|r| r.is_ok()).count();
         eprintln!("Edge update completed: {}/{} successful", success_count, results.len());
-        tokio::time::sleep(interval).await;
+        // Follow the prices, so the next quotes of every token keep the value of the start amount
+        graph.reprice();
+        // The index must be the one of the topology the snapshot is taken on
+        if graph.version() != index_version {
+            cycle_index = follow_topology(&mut graph, negative_cycles, max_path_len);
+            index_version = graph.version();
+        }
+
+        // Search over the warm quotes, no quote is requested here
+        let snapshot = QuoteSnapshot::capture(&graph);
+        let opportunities = match &cycle_index {
+            Some(cycle_index) => {
+                let search_result = search_index(&snapshot, cycle_index, max_quote_age);
+                let profitable_count = search_result.opportunities.iter().filter(|o| o.is_profitable()).count();
+                eprintln!("Cached search: {} cycles, {} profitable, {} edges skipped",
+                          search_result.opportunities.len(), profitable_count, search_result.skipped_edges.len());
+                search_result.opportunities
+            },
+            None => {
+                let search_result = search_negative_cycles(&snapshot, max_quote_age);
+                let profitable_count = search_result.opportunities.iter().filter(|o| o.is_profitable()).count();
+                eprintln!("Negative cycle search: {} cycles through the start token, {} profitable, {} other cycles, {} edges skipped",
+                          search_result.opportunities.len(), profitable_count, search_result.other_cycles.len(),
+                          search_result.skipped_edges.len());
+                search_result.opportunities
+            },
+        };
+        // Size the most profitable cycle on the quote ladders, still without requesting a quote
+        if let Some(best) = opportunities.iter().filter(|o| o.is_profitable()).max_by_key(|o| o.gross_profit) {
+            let sized = optimize_trade_size_estimated(&snapshot, &best.edge_ids(), snapshot.start_amount / 10,
+                                                      snapshot.start_amount.saturating_mul(10), 12, max_quote_age);
+            if let Some(sized_best) = sized.best {
+                eprintln!("Best cycle {}: estimated profit {} at amount {}",
+                          sized_best.symbols(&snapshot.topology).join(" -> "), sized_best.gross_profit, sized_best.start_amount);
+            }
+        }
+
+        if let Some(discovery) = &mut discovery {
+            discovery.observe_snapshot(&snapshot, round_start);
+            let proposals = discovery.proposals(&graph.topology());
+            if !proposals.is_empty() {
+                if discovery.config.auto_add {
+                    match graph.apply_changes(&proposals) {
+                        Ok(new_edges) => {
+                            eprintln!("Discovery: topology version {}, {} new edges", graph.version(), new_edges.len());
+                            discovery.mark_proposed(&proposals);
+                        },
+                        // the mints are proposed again next round
+                        Err(e) => eprintln!("Discovery: change rejected: {}", e),
+                    }
+                } else {
+                    eprintln!("Discovery: proposed {:?}", proposals);
+                    discovery.mark_proposed(&proposals);
+                }
+            }
+        }
+
+        if let (Some(persist), Some(shutdown)) = (&persist, &mut shutdown) {
+            if last_save.elapsed() >= persist.interval {
+                save(&graph, persist);
+                last_save = std::time::Instant::now();
+            }
+            // A Ctrl-C is acted on between rounds, so the update round in progress finishes first
+            // changed() returns at once if Ctrl-C was pressed during the round
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {},
+                _ = shutdown.changed() => {
+                    save(&graph, persist);
+                    return;
+                },
+            }
+        } else {
+            tokio::time::sleep(interval).await;
+        }
     }
-}
\ No newline at end of file
+}
+
+fn save(graph: &dynamic_graph::DynamicGraph, persist: &PersistOptions) {
+    match persist::save_snapshot(graph, &persist.path) {
+        Ok(()) => eprintln!("Saved the graph to {}", persist.path.display()),
+        Err(e) => eprintln!("{}: {}", persist.path.display(), e),
+    }
+}
+
+fn apply_change(graph: &mut dynamic_graph::DynamicGraph, change: &[TopologyChange]) {
+    match graph.apply_changes(change) {
+        Ok(new_edges) => eprintln!("Topology version {}: {} new edges", graph.version(), new_edges.len()),
+        Err(e) => eprintln!("Topology change rejected: {}", e),
+    }
+}
+
+// The next changes sent to the updater, never if there is no channel
+async fn next_change(changes: &mut Option<mpsc::UnboundedReceiver<Vec<TopologyChange>>>) -> Option<Vec<TopologyChange>> {
+    match changes {
+        Some(changes) => changes.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Prune and enumerate the cycles through the start node again for the current topology, None in the negative-cycle mode
+fn follow_topology(graph: &mut dynamic_graph::DynamicGraph, negative_cycles: bool, max_path_len: usize) -> Option<CycleIndex> {
+    let topology = graph.topology();
+    // Bellman-Ford finds cycles of any length, a simple cycle has at most n_node edges
+    report_pruning(graph, if negative_cycles { topology.n_node() } else { max_path_len });
+    (!negative_cycles).then(|| CycleIndex::through_node(&topology, graph.start_node(), max_path_len))
+}
+
+// Stop updating the edges no cycle through the start node can use, and say which tokens are dead
+fn report_pruning(graph: &mut dynamic_graph::DynamicGraph, max_path_len: usize) {
+    let pruning = graph.prune(max_path_len);
+    eprintln!("Pruning: {}/{} edges live, dead tokens: {:?}",
+              pruning.n_live_edges(), pruning.live_edges.len(), pruning.dead_tokens(&graph.topology()));
+}
@@ -2,36 +2,364 @@
 // The input should guarantee that nodes are compactly numbered from 1 to n
 // Node id 0 is reserved as an empty node
 
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+use serde::{Deserialize, Serialize};
+use crate::graph::csr_graph::CsrGraph;
+use crate::jupiter::quote::QuoteParams;
+use crate::mints::mints::TokenInfo;
+
 pub struct StaticGraph {
     pub head: Vec<Option<usize>>, // head[i] is the index of the first edge from node i
     pub to: Vec<usize>, // to[i] is the destination node of edge i
     pub next: Vec<Option<usize>>, // next[i] is the index of the next edge from the same node as edge i
     pub edge_info: Vec<EdgeInfo>, // edge_info[i] is the information of edge i
+    pub rhead: Vec<Option<usize>>, // rhead[i] is the index of the first edge into node i
+    pub rnext: Vec<Option<usize>>, // rnext[i] is the index of the next edge into the same node as edge i
+    pub nodes: Vec<NodeInfo>, // nodes[i] is the token of node i, nodes[0] is the empty node
+    mint2id: HashMap<String, usize>,
+    frozen: OnceLock<CsrGraph>, // the CSR layout of the graph, built on first use and dropped by add_edge
 }
 
 pub struct EdgeInfo {
-    pub input_mint: String,
-    pub output_mint: String,
+    pub input_node: usize,
+    pub output_node: usize,
+    pub dex_filter: DexFilter, // restricts the venues the edge is quoted on, several edges can join the same nodes
+}
+
+// The dexes / excludeDexes restriction of a quote, the default lets Jupiter route through every DEX
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DexFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dexes: Option<Vec<String>>, // only route through these DEXes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude_dexes: Option<Vec<String>>, // never route through these DEXes
+}
+
+impl DexFilter {
+    pub fn only(dexes: &[&str]) -> Self {
+        Self {
+            dexes: Some(dexes.iter().map(|dex| dex.to_string()).collect()),
+            exclude_dexes: None,
+        }
+    }
+
+    pub fn excluding(dexes: &[&str]) -> Self {
+        Self {
+            dexes: None,
+            exclude_dexes: Some(dexes.iter().map(|dex| dex.to_string()).collect()),
+        }
+    }
+
+    pub fn is_unrestricted(&self) -> bool {
+        self.dexes.is_none() && self.exclude_dexes.is_none()
+    }
+
+    // A short label of the restriction, e.g. "Raydium" or "!Raydium,Orca V2", empty when unrestricted
+    pub fn label(&self) -> String {
+        let mut parts = vec![];
+        if let Some(dexes) = &self.dexes {
+            parts.push(dexes.join(","));
+        }
+        if let Some(exclude_dexes) = &self.exclude_dexes {
+            parts.push(format!("!{}", exclude_dexes.join(",")));
+        }
+        parts.join(" ")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    NodeOutOfRange { node: usize, n_node: usize }, // node ids are 1..=n_node, 0 is the empty node
+    SelfLoop { node: usize },
+    DuplicateEdge { from: usize, to: usize, edge_id: usize }, // same nodes and same DEX filter as edge_id
+    UnknownMint(String),
+    DuplicateMint(String),
+    DuplicateSymbol(String), // symbols name the tokens of a topology file, so they are unique too
+    EdgeMismatch { edge_id: usize }, // edge_info disagrees with the chain forward star arrays
+    MintIndexMismatch { node: usize, mint: String }, // the mint of the node doesn't map back to the node
+    MissingEdge { input_mint: String, output_mint: String },
+    RemoveStartNode(String), // the start token can't be removed from a running graph
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::NodeOutOfRange { node, n_node } => write!(f, "Node {} is out of range 1..={}", node, n_node),
+            GraphError::SelfLoop { node } => write!(f, "Edge from node {} to itself", node),
+            GraphError::DuplicateEdge { from, to, edge_id } => write!(f, "Edge {} -> {} duplicates edge {}", from, to, edge_id),
+            GraphError::UnknownMint(mint) => write!(f, "Mint {} is not a node", mint),
+            GraphError::DuplicateMint(mint) => write!(f, "Mint {} is used by several nodes", mint),
+            GraphError::DuplicateSymbol(symbol) => write!(f, "Symbol {} is used by several nodes", symbol),
+            GraphError::EdgeMismatch { edge_id } => write!(f, "The info of edge {} doesn't match its endpoints", edge_id),
+            GraphError::MintIndexMismatch { node, mint } => write!(f, "Mint {} of node {} doesn't map to the node", mint, node),
+            GraphError::MissingEdge { input_mint, output_mint } => write!(f, "No edge from {} to {}", input_mint, output_mint),
+            GraphError::RemoveStartNode(mint) => write!(f, "Mint {} is the start node and can't be removed", mint),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub mint: String,
+    pub symbol: String,
+    pub decimals: Option<u8>, // None if unknown, e.g. for a mint found by discovery.rs
+}
+
+impl NodeInfo {
+    // The number of smallest units in one whole token, None if the decimals are unknown
+    pub fn unit(&self) -> Option<f64> {
+        self.decimals.map(|decimals| 10f64.powi(decimals as i32))
+    }
+}
+
+// Walks one of the linked lists of the chain forward star
+pub struct EdgeIter<'a> {
+    next: &'a [Option<usize>],
+    current: Option<usize>,
+}
+
+impl Iterator for EdgeIter<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let edge_idx = self.current?;
+        self.current = self.next[edge_idx];
+        Some(edge_idx)
+    }
+}
+
+impl From<&TokenInfo> for NodeInfo {
+    fn from(token: &TokenInfo) -> Self {
+        Self {
+            mint: token.mint.to_string(),
+            symbol: token.name.to_string(),
+            decimals: Some(token.decimals as u8),
+        }
+    }
 }
 
 impl StaticGraph {
-    pub fn new(n_node: usize) -> Self {
+    // nodes[i] becomes node i + 1
+    pub fn new(nodes: Vec<NodeInfo>) -> Self {
+        let n_node = nodes.len();
+        let mint2id = nodes.iter()
+            .enumerate()
+            .map(|(i, node)| (node.mint.clone(), i + 1))
+            .collect();
         Self {
             head: vec![None; n_node + 1], // head[0] is reserved for the empty node
             to: vec![0; 0],
             next: vec![None; 0],
             edge_info: vec![],
+            rhead: vec![None; n_node + 1],
+            rnext: vec![None; 0],
+            nodes: std::iter::once(NodeInfo::default()).chain(nodes).collect(),
+            mint2id,
+            frozen: OnceLock::new(),
         }
     }
     
-    pub fn add_edge(&mut self, from: usize, to: usize, input_mint: String, output_mint: String)
+    // Returns the id of the new edge
+    pub fn add_edge(&mut self, from: usize, to: usize) -> Result<usize, GraphError>
+    {
+        self.add_edge_via(from, to, DexFilter::default())
+    }
+
+    // Add an edge that is only quoted on the venues allowed by dex_filter
+    // Parallel edges are allowed as long as their DEX filters differ
+    pub fn add_edge_via(&mut self, from: usize, to: usize, dex_filter: DexFilter) -> Result<usize, GraphError>
     {
+        for node in [from, to] {
+            if node == 0 || node > self.n_node() {
+                return Err(GraphError::NodeOutOfRange { node, n_node: self.n_node() });
+            }
+        }
+        if from == to {
+            return Err(GraphError::SelfLoop { node: from });
+        }
+        if let Some(edge_id) = self.out_edges(from).find(|&e| self.to[e] == to && self.edge_info[e].dex_filter == dex_filter) {
+            return Err(GraphError::DuplicateEdge { from, to, edge_id });
+        }
         self.to.push(to);
         self.edge_info.push(EdgeInfo {
-            input_mint,
-            output_mint,
+            input_node: from,
+            output_node: to,
+            dex_filter,
         });
         self.next.push(self.head[from]);
         self.head[from] = Some(self.to.len() - 1);
+        self.rnext.push(self.rhead[to]);
+        self.rhead[to] = Some(self.to.len() - 1);
+        self.frozen = OnceLock::new();
+        Ok(self.to.len() - 1)
+    }
+
+    // A CSR copy of the graph, for traversals once the graph is built
+    pub fn freeze(&self) -> CsrGraph {
+        CsrGraph::from_static(self)
+    }
+
+    // The CSR layout of the graph, built once and reused until the next add_edge
+    pub fn frozen(&self) -> &CsrGraph {
+        self.frozen.get_or_init(|| self.freeze())
+    }
+
+    // The ids of the edges from `node`, most recently added first
+    pub fn out_edges(&self, node: usize) -> EdgeIter<'_> {
+        EdgeIter {
+            next: &self.next,
+            current: self.head[node],
+        }
+    }
+
+    // The ids of the edges into `node`, most recently added first
+    pub fn in_edges(&self, node: usize) -> EdgeIter<'_> {
+        EdgeIter {
+            next: &self.rnext,
+            current: self.rhead[node],
+        }
+    }
+
+    // (from, to, info) of an edge
+    pub fn edge(&self, edge_idx: usize) -> (usize, usize, &EdgeInfo) {
+        let info = &self.edge_info[edge_idx];
+        (info.input_node, self.to[edge_idx], info)
+    }
+
+    pub fn n_node(&self) -> usize {
+        self.head.len() - 1
+    }
+
+    pub fn n_edge(&self) -> usize {
+        self.to.len()
+    }
+
+    pub fn node(&self, id: usize) -> &NodeInfo {
+        &self.nodes[id]
+    }
+
+    pub fn node_id(&self, mint: &str) -> Option<usize> {
+        self.mint2id.get(mint).copied()
+    }
+
+    pub fn node_by_mint(&self, mint: &str) -> Option<&NodeInfo> {
+        self.node_id(mint).map(|id| &self.nodes[id])
+    }
+
+    pub fn input_mint(&self, edge_idx: usize) -> &str {
+        &self.nodes[self.edge_info[edge_idx].input_node].mint
+    }
+
+    pub fn output_mint(&self, edge_idx: usize) -> &str {
+        &self.nodes[self.edge_info[edge_idx].output_node].mint
+    }
+
+    // The id of the edge from input_mint to output_mint with exactly this DEX filter
+    pub fn find_edge(&self, input_mint: &str, output_mint: &str, dex_filter: &DexFilter) -> Option<usize> {
+        let (from, to) = (self.node_id(input_mint)?, self.node_id(output_mint)?);
+        self.out_edges(from).find(|&edge_idx| self.to[edge_idx] == to && self.edge_info[edge_idx].dex_filter == *dex_filter)
+    }
+
+    // The parameters to quote `amount` through the edge, with the edge's DEX restriction
+    pub fn quote_params(&self, edge_idx: usize, amount: u64) -> QuoteParams {
+        let mut params = QuoteParams::new(self.input_mint(edge_idx).to_string(), self.output_mint(edge_idx).to_string(), amount);
+        let dex_filter = &self.edge_info[edge_idx].dex_filter;
+        if let Some(dexes) = &dex_filter.dexes {
+            params = params.with_dexes(dexes.clone());
+        }
+        if let Some(exclude_dexes) = &dex_filter.exclude_dexes {
+            params = params.with_exclude_dexes(exclude_dexes.clone());
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(mint: &str, decimals: Option<u8>) -> NodeInfo {
+        NodeInfo { mint: mint.to_string(), symbol: mint.to_lowercase(), decimals }
+    }
+
+    #[test]
+    fn nodes_are_found_by_mint_from_id_1() {
+        let mut graph = StaticGraph::new(vec![node("A", Some(6)), node("B", None)]);
+        let edge_idx = graph.add_edge(2, 1).unwrap();
+        assert_eq!(graph.n_node(), 2);
+        assert_eq!(graph.node(0), &NodeInfo::default());
+        assert_eq!(graph.node_id("A"), Some(1));
+        assert_eq!(graph.node_id("C"), None);
+        assert_eq!(graph.node_by_mint("B").map(|node| node.symbol.as_str()), Some("b"));
+        assert_eq!((graph.input_mint(edge_idx), graph.output_mint(edge_idx)), ("B", "A"));
+        assert_eq!(graph.edge_info[edge_idx].input_node, 2);
+    }
+
+    #[test]
+    fn unit_is_one_whole_token() {
+        assert_eq!(node("A", Some(6)).unit(), Some(1_000_000.0));
+        assert_eq!(node("A", Some(0)).unit(), Some(1.0));
+        assert_eq!(node("A", None).unit(), None);
+    }
+
+    #[test]
+    fn out_and_in_edges_walk_the_most_recent_edge_first() {
+        let mut graph = StaticGraph::new(vec![node("A", Some(6)), node("B", Some(6)), node("C", Some(6))]);
+        let a_b = graph.add_edge(1, 2).unwrap();
+        let a_c = graph.add_edge(1, 3).unwrap();
+        let c_b = graph.add_edge(3, 2).unwrap();
+        assert_eq!(graph.out_edges(1).collect::<Vec<_>>(), [a_c, a_b]);
+        assert_eq!(graph.in_edges(2).collect::<Vec<_>>(), [c_b, a_b]);
+        assert_eq!(graph.out_edges(2).count(), 0);
+        assert_eq!(graph.in_edges(1).count(), 0);
+        let (from, to, info) = graph.edge(c_b);
+        assert_eq!((from, to, info.output_node), (3, 2, 2));
+    }
+
+    #[test]
+    fn parallel_edges_need_different_dex_filters() {
+        let mut graph = StaticGraph::new(vec![node("A", Some(6)), node("B", Some(6))]);
+        let any = graph.add_edge(1, 2).unwrap();
+        let raydium = graph.add_edge_via(1, 2, DexFilter::only(&["Raydium"])).unwrap();
+        let not_raydium = graph.add_edge_via(1, 2, DexFilter::excluding(&["Raydium"])).unwrap();
+        assert_eq!(graph.add_edge_via(1, 2, DexFilter::only(&["Raydium"])),
+                   Err(GraphError::DuplicateEdge { from: 1, to: 2, edge_id: raydium }));
+        assert_eq!(graph.find_edge("A", "B", &DexFilter::default()), Some(any));
+        assert_eq!(graph.find_edge("A", "B", &DexFilter::excluding(&["Raydium"])), Some(not_raydium));
+        assert_eq!(graph.find_edge("A", "B", &DexFilter::only(&["Orca"])), None);
+    }
+
+    #[test]
+    fn quote_params_carry_the_dex_filter() {
+        let mut graph = StaticGraph::new(vec![node("A", Some(6)), node("B", Some(6))]);
+        let any = graph.add_edge(1, 2).unwrap();
+        let filtered = graph.add_edge_via(1, 2, DexFilter {
+            dexes: Some(vec!["Raydium".to_string(), "Orca V2".to_string()]),
+            exclude_dexes: Some(vec!["Meteora".to_string()]),
+        }).unwrap();
+        let query = |edge_idx| graph.quote_params(edge_idx, 1000).to_query();
+        assert_eq!(query(any), [("inputMint".to_string(), "A".to_string()), ("outputMint".to_string(), "B".to_string()),
+                                ("amount".to_string(), "1000".to_string())]);
+        let filtered_query = query(filtered);
+        assert!(filtered_query.contains(&("dexes".to_string(), "Raydium,Orca V2".to_string())));
+        assert!(filtered_query.contains(&("excludeDexes".to_string(), "Meteora".to_string())));
+        assert_eq!(graph.edge_info[filtered].dex_filter.label(), "Raydium,Orca V2 !Meteora");
+        assert_eq!(DexFilter::default().label(), "");
+        assert!(DexFilter::default().is_unrestricted() && !DexFilter::excluding(&["Orca"]).is_unrestricted());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn add_edge_rejects_the_edges_it_can_not_number() {
+        let mut graph = StaticGraph::new(vec![node("A", Some(6)), node("B", Some(6))]);
+        assert_eq!(graph.add_edge(0, 1), Err(GraphError::NodeOutOfRange { node: 0, n_node: 2 }));
+        assert_eq!(graph.add_edge(1, 3), Err(GraphError::NodeOutOfRange { node: 3, n_node: 2 }));
+        assert_eq!(graph.add_edge(1, 1), Err(GraphError::SelfLoop { node: 1 }));
+        assert_eq!(graph.add_edge(1, 2), Ok(0));
+        assert_eq!(graph.add_edge(1, 2), Err(GraphError::DuplicateEdge { from: 1, to: 2, edge_id: 0 }));
+        assert_eq!(graph.n_edge(), 1);
+    }
+}
@@ -0,0 +1,275 @@
+// Load the tokens and pairs to watch from a JSON file instead of the constants in mints.rs
+//
+// {
+//     "tokens": [
+//         { "symbol": "WSOL", "mint": "So11111111111111111111111111111111111111112", "decimals": 9, "reference_price": 1 },
+//         { "symbol": "USDC", "mint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", "decimals": 6, "reference_price": 0.0067 }
+//     ],
+//     "edges": [
+//         { "from": "WSOL", "to": "USDC" },
+//         { "from": "USDC", "to": "WSOL", "bidirectional": false },
+//         { "from": "WSOL", "to": "USDC", "dexes": ["Raydium"] },
+//         { "from": "WSOL", "to": "USDC", "exclude_dexes": ["Raydium"] }
+//     ],
+//     "start_token": "WSOL",
+//     "start_amount": 1000000000
+// }
+//
+// Edges refer to tokens by symbol or by mint and are bidirectional unless stated otherwise.
+// An edge can be restricted to some DEXes (or exclude some), so the same pair can be listed once per venue.
+// The start token defaults to the first token, and the start amount to one whole start token.
+// reference_price is optional, the price of one whole token in a unit common to all tokens, so every edge is
+// quoted at the value of the start amount. If any token has one the start token needs one too, e.g. 1 for prices
+// in start tokens. See quote_sizing.rs.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::graph::builder::GraphBuilder;
+use crate::graph::dynamic_graph::DynamicGraph;
+use crate::graph::static_graph::{DexFilter, GraphError, NodeInfo, StaticGraph};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TokenEntry {
+    pub symbol: String,
+    pub mint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals: Option<u8>, // may be left out when unknown, except for the start token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_price: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EdgeEntry {
+    pub from: String, // symbol or mint
+    pub to: String, // symbol or mint
+    #[serde(default = "default_bidirectional")]
+    pub bidirectional: bool,
+    #[serde(flatten)]
+    pub dex_filter: DexFilter,
+}
+
+fn default_bidirectional() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TopologyFile {
+    pub tokens: Vec<TokenEntry>,
+    pub edges: Vec<EdgeEntry>,
+    pub start_token: Option<String>, // symbol or mint
+    pub start_amount: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum TopologyError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    NoTokens,
+    DuplicateSymbol(String),
+    DuplicateMint(String),
+    InvalidDecimals { symbol: String, decimals: u8 },
+    InvalidReferencePrice { symbol: String, price: f64 },
+    UnknownStartDecimals(String),
+    MissingStartReferencePrice(String),
+    UnknownToken(String),
+    SelfLoop(String),
+    ZeroStartAmount,
+    Graph(GraphError),
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopologyError::Io(e) => write!(f, "Can't read the topology file: {}", e),
+            TopologyError::Parse(e) => write!(f, "Can't parse the topology file: {}", e),
+            TopologyError::NoTokens => write!(f, "The topology has no token"),
+            TopologyError::DuplicateSymbol(symbol) => write!(f, "Duplicate token symbol {}", symbol),
+            TopologyError::DuplicateMint(mint) => write!(f, "Duplicate token mint {}", mint),
+            TopologyError::InvalidDecimals { symbol, decimals } => write!(f, "Token {} has invalid decimals {}", symbol, decimals),
+            TopologyError::InvalidReferencePrice { symbol, price } => write!(f, "Token {} has invalid reference price {}", symbol, price),
+            TopologyError::UnknownStartDecimals(symbol) => write!(f, "The start token {} has unknown decimals", symbol),
+            TopologyError::MissingStartReferencePrice(symbol) => write!(f, "Some tokens have a reference price but the start token {} doesn't", symbol),
+            TopologyError::UnknownToken(token) => write!(f, "Unknown token {}", token),
+            TopologyError::SelfLoop(token) => write!(f, "Edge from {} to itself", token),
+            TopologyError::ZeroStartAmount => write!(f, "The start amount is 0"),
+            TopologyError::Graph(e) => write!(f, "Invalid graph: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TopologyError {}
+
+pub struct LoadedTopology {
+    pub graph: StaticGraph,
+    pub start_node: usize,
+    pub start_amount: u64,
+    pub reference_prices: HashMap<String, f64>, // mint -> reference_price of the tokens that have one
+}
+
+impl LoadedTopology {
+    pub fn into_dynamic_graph(self) -> DynamicGraph {
+        let mut graph = DynamicGraph::new(Arc::from(self.graph), self.start_node, self.start_amount);
+        graph.reference_prices = self.reference_prices;
+        graph
+    }
+}
+
+impl TopologyFile {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, TopologyError> {
+        let content = std::fs::read_to_string(path).map_err(TopologyError::Io)?;
+        Self::from_json(&content)
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, TopologyError> {
+        serde_json::from_str(content).map_err(TopologyError::Parse)
+    }
+
+    // Find a token by symbol or by mint
+    pub fn find_token(&self, token: &str) -> Option<&TokenEntry> {
+        self.tokens.iter().find(|t| t.symbol == token || t.mint == token)
+    }
+
+    fn resolve(&self, token: &str) -> Result<&TokenEntry, TopologyError> {
+        self.find_token(token).ok_or_else(|| TopologyError::UnknownToken(token.to_string()))
+    }
+
+    pub fn validate(&self) -> Result<(), TopologyError> {
+        if self.tokens.is_empty() {
+            return Err(TopologyError::NoTokens);
+        }
+        let mut symbols = HashSet::new();
+        let mut mints = HashSet::new();
+        for token in &self.tokens {
+            if !symbols.insert(&token.symbol) {
+                return Err(TopologyError::DuplicateSymbol(token.symbol.clone()));
+            }
+            if !mints.insert(&token.mint) {
+                return Err(TopologyError::DuplicateMint(token.mint.clone()));
+            }
+            // amounts are u64 in the smallest unit, and with 19 decimals one whole token is already more than half of
+            // u64::MAX, so an amount of 2 tokens (e.g. a doubled trade size) would overflow
+            if let Some(decimals) = token.decimals && decimals > 18 {
+                return Err(TopologyError::InvalidDecimals { symbol: token.symbol.clone(), decimals });
+            }
+            if let Some(price) = token.reference_price && !(price.is_finite() && price > 0.0) {
+                return Err(TopologyError::InvalidReferencePrice { symbol: token.symbol.clone(), price });
+            }
+        }
+        for edge in &self.edges {
+            let from = self.resolve(&edge.from)?;
+            let to = self.resolve(&edge.to)?;
+            if from.mint == to.mint {
+                return Err(TopologyError::SelfLoop(edge.from.clone()));
+            }
+        }
+        let start_token = match &self.start_token {
+            Some(start_token) => self.resolve(start_token)?,
+            None => &self.tokens[0],
+        };
+        // the quote amount of every edge is the value of the start amount, see quote_sizing.rs
+        if start_token.decimals.is_none() {
+            return Err(TopologyError::UnknownStartDecimals(start_token.symbol.clone()));
+        }
+        // the prices can be in any unit, e.g. USD, only the price of the start token says which
+        if start_token.reference_price.is_none() && self.tokens.iter().any(|token| token.reference_price.is_some()) {
+            return Err(TopologyError::MissingStartReferencePrice(start_token.symbol.clone()));
+        }
+        if self.start_amount == Some(0) {
+            return Err(TopologyError::ZeroStartAmount);
+        }
+        Ok(())
+    }
+
+    pub fn build(&self) -> Result<LoadedTopology, TopologyError> {
+        self.validate()?;
+        let mut builder = self.tokens.iter().fold(GraphBuilder::new(), |builder, token| builder.node(NodeInfo {
+            mint: token.mint.clone(),
+            symbol: token.symbol.clone(),
+            decimals: token.decimals,
+        }));
+        for edge in &self.edges {
+            let from = &self.resolve(&edge.from)?.mint;
+            let to = &self.resolve(&edge.to)?.mint;
+            builder = if edge.bidirectional {
+                builder.mint_pair_via(from, to, edge.dex_filter.clone())
+            } else {
+                builder.mint_one_way_via(from, to, edge.dex_filter.clone())
+            };
+        }
+        let graph = builder.build().map_err(TopologyError::Graph)?;
+
+        let start_token = match &self.start_token {
+            Some(start_token) => self.resolve(start_token)?,
+            None => &self.tokens[0],
+        };
+        let start_node = graph.node_id(&start_token.mint).unwrap();
+        let start_amount = self.start_amount.unwrap_or(10u64.pow(start_token.decimals.unwrap_or(0) as u32));
+        let reference_prices = self.tokens.iter()
+            .filter_map(|token| Some((token.mint.clone(), token.reference_price?)))
+            .collect();
+        Ok(LoadedTopology {
+            graph,
+            start_node,
+            start_amount,
+            reference_prices,
+        })
+    }
+}
+
+pub fn load_topology<P: AsRef<Path>>(path: P) -> Result<LoadedTopology, TopologyError> {
+    TopologyFile::from_path(path)?.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(json: &str) -> Result<LoadedTopology, TopologyError> {
+        TopologyFile::from_json(json)?.build()
+    }
+
+    #[test]
+    fn builds_bidirectional_and_one_way_edges() {
+        let loaded = build(r#"{
+            "tokens": [
+                { "symbol": "WSOL", "mint": "So1", "decimals": 9 },
+                { "symbol": "USDC", "mint": "EPj", "decimals": 6 }
+            ],
+            "edges": [
+                { "from": "WSOL", "to": "USDC" },
+                { "from": "EPj", "to": "So1", "bidirectional": false, "dexes": ["Raydium"] }
+            ]
+        }"#).unwrap();
+        assert_eq!(loaded.graph.n_edge(), 3);
+        assert_eq!(loaded.start_node, loaded.graph.node_id("So1").unwrap());
+        // one whole start token
+        assert_eq!(loaded.start_amount, 1_000_000_000);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let cases = [
+            (r#"{ "tokens": [], "edges": [] }"#, "NoTokens"),
+            (r#"{ "tokens": [{ "symbol": "A", "mint": "a", "decimals": 6 }, { "symbol": "A", "mint": "b", "decimals": 6 }], "edges": [] }"#, "DuplicateSymbol"),
+            (r#"{ "tokens": [{ "symbol": "A", "mint": "a", "decimals": 6 }, { "symbol": "B", "mint": "a", "decimals": 6 }], "edges": [] }"#, "DuplicateMint"),
+            (r#"{ "tokens": [{ "symbol": "A", "mint": "a", "decimals": 19 }], "edges": [] }"#, "InvalidDecimals"),
+            (r#"{ "tokens": [{ "symbol": "A", "mint": "a", "decimals": 6, "reference_price": 0 }], "edges": [] }"#, "InvalidReferencePrice"),
+            (r#"{ "tokens": [{ "symbol": "A", "mint": "a", "decimals": 6 }], "edges": [{ "from": "A", "to": "B" }] }"#, "UnknownToken"),
+            (r#"{ "tokens": [{ "symbol": "A", "mint": "a", "decimals": 6 }], "edges": [{ "from": "A", "to": "a" }] }"#, "SelfLoop"),
+            (r#"{ "tokens": [{ "symbol": "A", "mint": "a", "decimals": 6 }], "edges": [], "start_token": "B" }"#, "UnknownToken"),
+            (r#"{ "tokens": [{ "symbol": "A", "mint": "a", "decimals": 6 }], "edges": [], "start_amount": 0 }"#, "ZeroStartAmount"),
+            (r#"{ "tokens": [{ "symbol": "A", "mint": "a" }], "edges": [] }"#, "UnknownStartDecimals"),
+            (r#"{ "tokens": [{ "symbol": "A", "mint": "a", "decimals": 6 }, { "symbol": "B", "mint": "b", "decimals": 6, "reference_price": 2 }], "edges": [] }"#, "MissingStartReferencePrice"),
+            (r#"{ "tokens": [{ "symbol": "A", "mint": "a", "decimals": 6 }, { "symbol": "B", "mint": "b", "decimals": 6 }],
+                  "edges": [{ "from": "A", "to": "B" }, { "from": "A", "to": "B", "bidirectional": false }] }"#, "Graph"),
+            (r#"{ "tokens": "A" }"#, "Parse"),
+        ];
+        for (json, expected) in cases {
+            let error = build(json).err().unwrap_or_else(|| panic!("{} should be rejected", json));
+            assert!(format!("{:?}", error).starts_with(expected), "{}: expected {}, got {:?}", json, expected, error);
+        }
+    }
+}
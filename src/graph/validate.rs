@@ -0,0 +1,168 @@
+// Checks of a built StaticGraph, to run before any quote is spent on it
+// A node that no cycle through the start node can visit only costs quotes: an isolated node has no edge at all,
+// and a node with no return path to the start node can be entered but never left back to the start token.
+// The metadata checks catch graphs whose public arrays were edited by hand.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use crate::graph::static_graph::{GraphError, StaticGraph};
+
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub isolated_nodes: Vec<usize>, // nodes without any in or out edge
+    pub unreachable_nodes: Vec<usize>, // nodes that can't be reached from the start node
+    pub no_return_path: Vec<usize>, // nodes from which the start node can't be reached
+    pub metadata_errors: Vec<GraphError>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.isolated_nodes.is_empty()
+            && self.unreachable_nodes.is_empty()
+            && self.no_return_path.is_empty()
+            && self.metadata_errors.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "The graph is valid");
+        }
+        if !self.isolated_nodes.is_empty() {
+            writeln!(f, "Isolated nodes: {:?}", self.isolated_nodes)?;
+        }
+        if !self.unreachable_nodes.is_empty() {
+            writeln!(f, "Nodes unreachable from the start node: {:?}", self.unreachable_nodes)?;
+        }
+        if !self.no_return_path.is_empty() {
+            writeln!(f, "Nodes with no return path to the start node: {:?}", self.no_return_path)?;
+        }
+        for e in &self.metadata_errors {
+            writeln!(f, "{}", e)?;
+        }
+        Ok(())
+    }
+}
+
+impl StaticGraph {
+    pub fn validate(&self, start_node: usize) -> ValidationReport {
+        let mut report = ValidationReport {
+            metadata_errors: self.metadata_errors(),
+            ..Default::default()
+        };
+        if start_node == 0 || start_node > self.n_node() {
+            report.metadata_errors.push(GraphError::NodeOutOfRange { node: start_node, n_node: self.n_node() });
+            return report;
+        }
+        let reachable = self.reach(start_node, |node| self.out_edges(node).map(|e| self.to[e]).collect());
+        let returning = self.reach(start_node, |node| self.in_edges(node).map(|e| self.edge_info[e].input_node).collect());
+        for node in 1..=self.n_node() {
+            if self.out_edges(node).next().is_none() && self.in_edges(node).next().is_none() {
+                report.isolated_nodes.push(node);
+                continue;
+            }
+            if !reachable[node] {
+                report.unreachable_nodes.push(node);
+            }
+            if !returning[node] {
+                report.no_return_path.push(node);
+            }
+        }
+        report
+    }
+
+    // The errors add_edge would have refused, plus mints that don't map back to their node
+    fn metadata_errors(&self) -> Vec<GraphError> {
+        let mut errors = vec![];
+        let mut nodes_by_mint: HashMap<&str, usize> = HashMap::new();
+        for node in 1..=self.n_node() {
+            let mint = &self.node(node).mint;
+            if nodes_by_mint.insert(mint, node).is_some() {
+                errors.push(GraphError::DuplicateMint(mint.clone()));
+            }
+            if self.node_id(mint) != Some(node) {
+                errors.push(GraphError::MintIndexMismatch { node, mint: mint.clone() });
+            }
+        }
+        for node in 0..=self.n_node() {
+            for edge_idx in self.out_edges(node) {
+                let (from, to, info) = self.edge(edge_idx);
+                if from != node || info.output_node != to {
+                    errors.push(GraphError::EdgeMismatch { edge_id: edge_idx });
+                } else if to == 0 || to > self.n_node() {
+                    errors.push(GraphError::NodeOutOfRange { node: to, n_node: self.n_node() });
+                } else if from == to {
+                    errors.push(GraphError::SelfLoop { node });
+                } else if self.out_edges(node)
+                    .any(|e| e > edge_idx && self.to[e] == to && self.edge_info[e].dex_filter == info.dex_filter) {
+                    errors.push(GraphError::DuplicateEdge { from, to, edge_id: edge_idx });
+                }
+            }
+        }
+        errors
+    }
+
+    // reached[i] = true if node i can be reached from `from` through the neighbours given by `next`
+    fn reach<F: Fn(usize) -> Vec<usize>>(&self, from: usize, next: F) -> Vec<bool> {
+        let mut reached = vec![false; self.head.len()];
+        reached[from] = true;
+        let mut queue = VecDeque::from([from]);
+        while let Some(node) = queue.pop_front() {
+            for neighbour in next(node) {
+                if neighbour < reached.len() && !reached[neighbour] {
+                    reached[neighbour] = true;
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        reached
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::static_graph::{DexFilter, GraphError, StaticGraph};
+
+    // S <-> A, S -> B with no way back, C -> S that S can't reach, and D without any edge
+    fn graph() -> StaticGraph {
+        GraphBuilder::new()
+            .test_nodes(&[("S", Some(6)), ("A", Some(6)), ("B", Some(6)), ("C", Some(6)), ("D", Some(6))])
+            .mint_pair("S", "A")
+            .mint_one_way("S", "B")
+            .mint_one_way("C", "S")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn reports_the_nodes_no_start_cycle_can_visit() {
+        let graph = graph();
+        let report = graph.validate(1);
+        assert!(!report.is_ok());
+        assert_eq!(report.isolated_nodes, [5]);
+        assert_eq!(report.unreachable_nodes, [4]);
+        assert_eq!(report.no_return_path, [3]);
+        assert!(report.metadata_errors.is_empty());
+        assert!(report.to_string().contains("Isolated nodes: [5]"));
+
+        let valid = GraphBuilder::new().test_nodes(&[("S", Some(6)), ("A", Some(6))]).mint_pair("S", "A").build().unwrap();
+        assert!(valid.validate(1).is_ok());
+        assert_eq!(valid.validate(1).to_string(), "The graph is valid");
+    }
+
+    #[test]
+    fn reports_the_arrays_edited_by_hand() {
+        let mut graph = graph();
+        assert_eq!(graph.validate(6).metadata_errors, [GraphError::NodeOutOfRange { node: 6, n_node: 5 }]);
+
+        let s_a = graph.find_edge("S", "A", &DexFilter::default()).unwrap();
+        graph.edge_info[s_a].output_node = 3;
+        graph.nodes[4].mint = "S".to_string();
+        let errors = graph.validate(1).metadata_errors;
+        assert!(errors.contains(&GraphError::EdgeMismatch { edge_id: s_a }));
+        assert!(errors.contains(&GraphError::DuplicateMint("S".to_string())));
+        assert!(errors.contains(&GraphError::MintIndexMismatch { node: 4, mint: "S".to_string() }));
+    }
+}
@@ -1 +1,4 @@
-pub(crate) mod quote;
\ No newline at end of file
+pub(crate) mod quote;
+
+// The quote function the binaries pass to the updater and the trade-size search
+pub use quote::quote_jupiter;
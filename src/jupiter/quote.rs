@@ -81,7 +81,17 @@ impl QuoteParams {
         }
     }
 
-    pub fn to_query(self) -> Vec<(String, String)> {
+    pub fn with_dexes(mut self, dexes: Vec<String>) -> Self {
+        self.dexes = Some(dexes);
+        self
+    }
+
+    pub fn with_exclude_dexes(mut self, exclude_dexes: Vec<String>) -> Self {
+        self.exclude_dexes = Some(exclude_dexes);
+        self
+    }
+
+    pub fn to_query(&self) -> Vec<(String, String)> {
         let mut query = vec![
             ("inputMint".to_string(), self.input_mint.clone()),
             ("outputMint".to_string(), self.output_mint.clone()),
@@ -122,6 +132,22 @@ impl QuoteParams {
         query
     }
 }
+
+#[cfg(test)]
+impl QuoteParams {
+    pub(crate) fn amount(&self) -> u64 {
+        self.amount
+    }
+}
+// The Jupiter instance the searches and the updater quote against
+pub const JUPITER_URL: &str = "http://64.130.36.228:18080";
+
+// Quote against JUPITER_URL, with the error made Send so the quote can run in a spawned task
+pub async fn quote_jupiter(params: QuoteParams) -> Result<QuoteResponse, Box<dyn std::error::Error + Send>> {
+    quote(JUPITER_URL, params).await
+        .map_err(|e| Box::new(std::io::Error::other(e.to_string())) as Box<dyn std::error::Error + Send>)
+}
+
 pub async fn quote(url: &str, params: QuoteParams) -> Result<QuoteResponse, Box<dyn std::error::Error>> {
     let client = reqwest::Client::new();
 
@@ -204,4 +230,29 @@ pub struct QuoteResponse {
     pub context_slot: Option<i64>,
     #[serde(rename = "timeTaken")]
     pub time_taken: Option<f64>,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+impl QuoteResponse {
+    // A quote of in_amount -> out_amount without route, for the tests of the modules that read quotes
+    pub(crate) fn for_test(input_mint: &str, output_mint: &str, in_amount: u64, out_amount: u64) -> Self {
+        Self {
+            input_mint: input_mint.to_string(),
+            in_amount: in_amount.to_string(),
+            output_mint: output_mint.to_string(),
+            out_amount: out_amount.to_string(),
+            other_amount_threshold: out_amount.to_string(),
+            swap_mode: "ExactIn".to_string(),
+            slippage_bps: 0,
+            platform_fee: None,
+            price_impact_pct: "0".to_string(),
+            route_plan: vec![],
+            context_slot: None,
+            time_taken: None,
+        }
+    }
+
+    // The quote of the params at out_amount, the answer of the fake quote functions of the unit tests
+    pub(crate) fn for_params(params: &QuoteParams, out_amount: u64) -> Self {
+        Self::for_test(&params.input_mint, &params.output_mint, params.amount, out_amount)
+    }
+}
@@ -1,8 +1,3 @@
-mod graph;
-mod jupiter;
-mod search;
-mod mints;
-
 #[tokio::main]
 async fn main() {
 }
@@ -2,9 +2,9 @@
 #[derive(Eq, PartialEq)]
 #[derive(Hash)]
 pub struct TokenInfo {
-    name: &'static str,
+    pub(crate) name: &'static str,
     pub(crate) mint: &'static str,
-    decimals: u64,
+    pub(crate) decimals: u64,
 }
 pub(crate) const WSOL: TokenInfo = TokenInfo {
     name: "WSOL",
@@ -53,4 +53,22 @@ const JLP: TokenInfo = TokenInfo {
     name: "JLP",
     mint: "27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4",
     decimals: 6,
-};
\ No newline at end of file
+};
+// The tokens the bot watches, WSOL first so that it gets node id 1
+pub(crate) const DEFAULT_TOKENS: [&TokenInfo; 6] = [&WSOL, &USDC, &USDT, &WETH, &FARTCOIN, &POPCAT];
+
+// Every pair is traded in both directions
+pub(crate) const DEFAULT_PAIRS: [(&TokenInfo, &TokenInfo); 12] = [
+    (&WSOL, &USDC),
+    (&WSOL, &USDT),
+    (&USDC, &USDT),
+    (&USDC, &WETH),
+    (&USDT, &WETH),
+    (&WSOL, &WETH),
+    (&USDC, &FARTCOIN),
+    (&USDT, &FARTCOIN),
+    (&WSOL, &FARTCOIN),
+    (&USDC, &POPCAT),
+    (&USDT, &POPCAT),
+    (&WSOL, &POPCAT),
+];
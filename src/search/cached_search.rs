@@ -0,0 +1,373 @@
+// Search over the quotes cached in a DynamicGraph, without any network call
+// An edge is quoted at one input amount, so a leg quoted with a different (chained) amount is estimated:
+// from the quote ladder of the edge if it has one, else by scaling the cached quote linearly,
+// i.e. with the cached exchange rate
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use serde::Serialize;
+use crate::graph::attr_table::{AttrStore, AttrTable};
+use crate::graph::cycle_index::CycleIndex;
+use crate::graph::dynamic_graph::DynamicGraph;
+use crate::graph::quote_ladder::QuoteLadder;
+use crate::graph::static_graph::StaticGraph;
+use crate::jupiter::quote::QuoteResponse;
+use crate::search::opportunity::{Leg, Opportunity};
+use crate::search::search::{push_status, BFSStatus};
+
+// The cached quotes of all edges at one point in time, an immutable table that the updater doesn't block on
+pub struct QuoteSnapshot {
+    pub topology: Arc<StaticGraph>,
+    pub start_node: usize,
+    pub start_amount: u64,
+    pub attrs: Arc<AttrTable>, // attrs.get(i) is the cached quote of edge i and its update time
+    pub version: u64, // the topology version of the DynamicGraph when the snapshot was taken
+    pub live_edges: Option<Arc<Vec<bool>>>, // the pruning of the DynamicGraph, pruned edges are skipped
+}
+
+impl QuoteSnapshot {
+    pub fn capture(graph: &DynamicGraph) -> Self {
+        Self::load(&graph.attrs)
+    }
+
+    // From a handle on the store of a DynamicGraph, e.g. in another task while the graph is being updated
+    // The topology, the pruning and the quotes all come from the same table, so they always match
+    pub fn load(store: &AttrStore) -> Self {
+        let attrs = store.load();
+        Self {
+            topology: attrs.topology.clone(),
+            start_node: attrs.start_node,
+            start_amount: attrs.start_amount,
+            version: attrs.version,
+            live_edges: attrs.live_edges.clone(),
+            attrs,
+        }
+    }
+
+    // The cached quote of the edge and its update time, None if the edge has never been quoted
+    pub fn quote(&self, edge_idx: usize) -> Option<(&QuoteResponse, Instant)> {
+        let attr = self.attrs.get(edge_idx);
+        attr.quote_response.as_ref().map(|rsp| (rsp, attr.last_updated))
+    }
+
+    pub fn n_edge(&self) -> usize {
+        self.attrs.len()
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub enum SkipReason {
+    NoQuote, // the edge has never been quoted successfully
+    Stale { age_ms: u64 }, // the cached quote is older than the maximum age
+    InvalidQuote, // the amounts in the cached quote can't be parsed or are zero
+    Pruned, // the edge can't be on a cycle through the start node, see pruning.rs
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SkippedEdge {
+    pub edge_id: usize,
+    pub reason: SkipReason,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CachedSearchResult {
+    pub opportunities: Vec<Opportunity>,
+    pub skipped_edges: Vec<SkippedEdge>,
+}
+
+pub fn search_cached(graph: &DynamicGraph, max_path_len: usize, max_quote_age: Duration) -> CachedSearchResult {
+    search_snapshot(&QuoteSnapshot::capture(graph), max_path_len, max_quote_age)
+}
+
+pub fn search_snapshot(snapshot: &QuoteSnapshot, max_path_len: usize, max_quote_age: Duration) -> CachedSearchResult {
+    let graph = snapshot.topology.as_ref();
+    let start_node_id = snapshot.start_node;
+
+    let (usable, skipped_edges) = usable_legs(snapshot, max_quote_age);
+
+    let mut bfs = CachedBfs {
+        graph,
+        usable: &usable,
+        start_node_id,
+        max_path_len,
+        queue: VecDeque::new(),
+        opportunities: vec![],
+    };
+    let mut initial = BFSStatus::new(graph.head.len(), max_path_len);
+    initial.current_amount = snapshot.start_amount;
+    bfs.expand(&initial, start_node_id);
+    while let Some(status) = bfs.queue.pop_front() {
+        bfs.expand(&status, graph.to[status.current_edge_id]);
+    }
+
+    CachedSearchResult {
+        opportunities: bfs.opportunities,
+        skipped_edges,
+    }
+}
+
+// Evaluate the indexed cycles through the start node instead of running a BFS
+pub fn search_index(snapshot: &QuoteSnapshot, index: &CycleIndex, max_quote_age: Duration) -> CachedSearchResult {
+    let cycle_ids: Vec<usize> = (0..index.cycles.len()).collect();
+    search_index_cycles(snapshot, index, &cycle_ids, max_quote_age)
+}
+
+// Evaluate only some of the indexed cycles, e.g. the ones touching the edges updated since the last search,
+// which can be found with CycleIndex::cycles_touching
+pub fn search_index_cycles(snapshot: &QuoteSnapshot, index: &CycleIndex, cycle_ids: &[usize], max_quote_age: Duration)
+    -> CachedSearchResult {
+    let (usable, skipped_edges) = usable_legs(snapshot, max_quote_age);
+    let mut opportunities = vec![];
+    for &cycle_idx in cycle_ids {
+        let Some(edges) = index.cycles[cycle_idx].edges_from(snapshot.start_node) else {
+            continue;
+        };
+        // a cycle with a skipped edge can't be evaluated
+        if let Some(opportunity) = estimate_cycle(&usable, &edges, snapshot.start_amount) {
+            opportunities.push(opportunity);
+        }
+    }
+    CachedSearchResult {
+        opportunities,
+        skipped_edges,
+    }
+}
+
+struct CachedBfs<'a> {
+    graph: &'a StaticGraph,
+    usable: &'a [Option<CachedEdge>], // usable[i] is the cached quote of edge i, None if the edge is skipped
+    start_node_id: usize,
+    max_path_len: usize,
+    queue: VecDeque<BFSStatus>,
+    opportunities: Vec<Opportunity>,
+}
+
+impl CachedBfs<'_> {
+    // Extend the status with every usable edge out of `node`
+    fn expand(&mut self, status: &BFSStatus, node: usize) {
+        for (edge_idx, to_node) in self.graph.frozen().out_edges(node) {
+            if status.visited[to_node] {
+                continue;
+            }
+            let Some(cached_edge) = &self.usable[edge_idx] else {
+                continue;
+            };
+
+            let mut new_status = status.clone();
+            new_status.current_edge_id = edge_idx;
+            new_status.visited[to_node] = true;
+            new_status.path[new_status.path_tail] = edge_idx;
+            new_status.path_tail += 1;
+
+            let leg = cached_edge.estimate(status.current_amount);
+            new_status.current_amount = leg.out_amount;
+            new_status.legs.push(Arc::new(leg));
+            push_status(new_status, self.graph, self.start_node_id, self.max_path_len, &mut self.queue, &mut self.opportunities);
+        }
+    }
+}
+
+// A cached quote that passed the checks of usable_legs
+pub(crate) struct CachedEdge {
+    pub(crate) leg: Leg, // the cached quote as a leg
+    pub(crate) ladder: Option<QuoteLadder>,
+}
+
+impl CachedEdge {
+    // Estimate the leg for in_amount, from the ladder if there is one
+    pub(crate) fn estimate(&self, in_amount: u64) -> Leg {
+        match &self.ladder {
+            Some(ladder) if self.leg.in_amount != in_amount => {
+                let mut leg = self.leg.clone();
+                leg.in_amount = in_amount;
+                leg.out_amount = ladder.estimate_out(in_amount);
+                leg
+            },
+            _ => scale_leg(&self.leg, in_amount),
+        }
+    }
+}
+
+// Quote the cycle (a list of edge ids) from the cached edges, None if one of them is skipped
+pub(crate) fn estimate_cycle(usable: &[Option<CachedEdge>], cycle: &[usize], amount: u64) -> Option<Opportunity> {
+    let mut legs: Vec<Leg> = Vec::with_capacity(cycle.len());
+    let mut amount = amount;
+    for &edge_idx in cycle {
+        let leg = usable[edge_idx].as_ref()?.estimate(amount);
+        amount = leg.out_amount;
+        legs.push(leg);
+    }
+    Some(Opportunity::new(legs))
+}
+
+// Validate every cached quote once, usable[i] is the cached quote of edge i, None if the edge is skipped
+pub(crate) fn usable_legs(snapshot: &QuoteSnapshot, max_quote_age: Duration) -> (Vec<Option<CachedEdge>>, Vec<SkippedEdge>) {
+    let now = Instant::now();
+    let wall_now = SystemTime::now();
+    let mut skipped_edges = vec![];
+    let mut usable: Vec<Option<CachedEdge>> = Vec::with_capacity(snapshot.n_edge());
+    for edge_idx in 0..snapshot.n_edge() {
+        if snapshot.live_edges.as_ref().is_some_and(|live| !live[edge_idx]) {
+            skipped_edges.push(SkippedEdge { edge_id: edge_idx, reason: SkipReason::Pruned });
+            usable.push(None);
+            continue;
+        }
+        let leg = match snapshot.quote(edge_idx) {
+            None => {
+                skipped_edges.push(SkippedEdge { edge_id: edge_idx, reason: SkipReason::NoQuote });
+                None
+            },
+            Some((quote_rsp, last_updated)) => {
+                let age = now.saturating_duration_since(last_updated);
+                if age > max_quote_age {
+                    skipped_edges.push(SkippedEdge { edge_id: edge_idx, reason: SkipReason::Stale { age_ms: age.as_millis() as u64 } });
+                    None
+                } else {
+                    match Leg::from_quote(edge_idx, quote_rsp, wall_now - age) {
+                        Some(leg) if leg.in_amount > 0 => Some(CachedEdge {
+                            leg,
+                            ladder: snapshot.attrs.get(edge_idx).ladder.clone(),
+                        }),
+                        _ => {
+                            skipped_edges.push(SkippedEdge { edge_id: edge_idx, reason: SkipReason::InvalidQuote });
+                            None
+                        }
+                    }
+                }
+            },
+        };
+        usable.push(leg);
+    }
+    (usable, skipped_edges)
+}
+
+// Estimate the leg for `in_amount` from a cached leg quoted at a different amount
+pub(crate) fn scale_leg(cached_leg: &Leg, in_amount: u64) -> Leg {
+    let mut leg = cached_leg.clone();
+    if cached_leg.in_amount != in_amount {
+        leg.out_amount = (in_amount as u128 * cached_leg.out_amount as u128 / cached_leg.in_amount as u128) as u64;
+        leg.in_amount = in_amount;
+    }
+    leg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::dynamic_graph::EdgeAttribute;
+    use crate::graph::static_graph::DexFilter;
+
+    // S <-> A <-> B <-> S, each edge quoted `age` ago at 1000 with the out amount of `rates`, unquoted if not listed
+    fn graph(rates: &[(&str, &str, u64, Duration)]) -> DynamicGraph {
+        let static_graph = GraphBuilder::new()
+            .test_nodes(&[("S", Some(6)), ("A", Some(6)), ("B", Some(6))])
+            .mint_pair("S", "A")
+            .mint_pair("A", "B")
+            .mint_pair("B", "S")
+            .build()
+            .unwrap();
+        let start_node = static_graph.node_id("S").unwrap();
+        let graph = DynamicGraph::new(Arc::new(static_graph), start_node, 2000);
+        let topology = graph.topology();
+        let updates = rates.iter().map(|&(from, to, out_amount, age)| {
+            (topology.find_edge(from, to, &DexFilter::default()).unwrap(), EdgeAttribute {
+                quote_response: Some(QuoteResponse::for_test(from, to, 1000, out_amount)),
+                last_updated: Instant::now() - age,
+                ..EdgeAttribute::new()
+            })
+        }).collect();
+        graph.attrs.set_many(&topology, updates);
+        graph
+    }
+
+    fn edges(graph: &StaticGraph, path: &[&str]) -> Vec<usize> {
+        path.windows(2).map(|pair| graph.find_edge(pair[0], pair[1], &DexFilter::default()).unwrap()).collect()
+    }
+
+    const FRESH: Duration = Duration::ZERO;
+
+    #[test]
+    fn chains_the_cached_rates_from_the_start_amount() {
+        let graph = graph(&[("S", "A", 1100, FRESH), ("A", "B", 1000, FRESH), ("B", "S", 1000, FRESH),
+                            ("A", "S", 800, FRESH)]);
+        let result = search_cached(&graph, 3, Duration::from_secs(60));
+        let topology = graph.topology();
+        // S -> B has no quote, so only the cycles starting with S -> A are found
+        assert_eq!(result.opportunities.len(), 2);
+        let two_legs = &result.opportunities[0];
+        assert_eq!(two_legs.edge_ids(), edges(&topology, &["S", "A", "S"]));
+        assert_eq!((two_legs.start_amount, two_legs.end_amount), (2000, 1760));
+        let three_legs = &result.opportunities[1];
+        assert_eq!(three_legs.edge_ids(), edges(&topology, &["S", "A", "B", "S"]));
+        assert_eq!(three_legs.legs.iter().map(|leg| leg.in_amount).collect::<Vec<_>>(), [2000, 2200, 2200]);
+        assert_eq!(three_legs.gross_profit, 200);
+    }
+
+    #[test]
+    fn skips_the_unquoted_and_stale_edges() {
+        let graph = graph(&[("S", "A", 1100, FRESH), ("A", "S", 1000, Duration::from_secs(120)), ("A", "B", 0, FRESH)]);
+        let topology = graph.topology();
+        let result = search_cached(&graph, 3, Duration::from_secs(60));
+        assert!(result.opportunities.is_empty());
+
+        let reason = |from, to| {
+            let edge_idx = topology.find_edge(from, to, &DexFilter::default()).unwrap();
+            result.skipped_edges.iter().find(|skipped| skipped.edge_id == edge_idx).map(|skipped| skipped.reason.clone())
+        };
+        assert!(reason("S", "A").is_none());
+        assert!(matches!(reason("A", "S"), Some(SkipReason::Stale { age_ms }) if age_ms >= 120_000));
+        // a zero out amount parses, the leg is usable but worthless
+        assert!(reason("A", "B").is_none());
+        assert!(matches!(reason("B", "S"), Some(SkipReason::NoQuote)));
+        assert_eq!(result.skipped_edges.len(), 4);
+    }
+
+    #[test]
+    fn skips_the_pruned_edges_and_the_quotes_without_input() {
+        let mut graph = graph(&[("S", "A", 1100, FRESH), ("A", "S", 1000, FRESH)]);
+        let topology = graph.topology();
+        let s_a = topology.find_edge("S", "A", &DexFilter::default()).unwrap();
+        graph.attrs.set_many(&topology, vec![(s_a, EdgeAttribute {
+            quote_response: Some(QuoteResponse::for_test("S", "A", 0, 1100)),
+            last_updated: Instant::now(),
+            ..EdgeAttribute::new()
+        })]);
+        let result = search_cached(&graph, 3, Duration::from_secs(60));
+        assert!(result.skipped_edges.iter().any(|skipped| skipped.edge_id == s_a && matches!(skipped.reason, SkipReason::InvalidQuote)));
+
+        // every edge is on a 2-cycle through S, only a max path length of 1 prunes them all
+        graph.prune(1);
+        let result = search_cached(&graph, 3, Duration::from_secs(60));
+        assert!(result.skipped_edges.iter().all(|skipped| matches!(skipped.reason, SkipReason::Pruned)));
+        assert_eq!(result.skipped_edges.len(), topology.n_edge());
+    }
+
+    #[test]
+    fn the_index_finds_the_same_cycles_as_the_bfs() {
+        let graph = graph(&[("S", "A", 1100, FRESH), ("A", "B", 1000, FRESH), ("B", "S", 1000, FRESH),
+                            ("A", "S", 800, FRESH), ("S", "B", 1000, FRESH), ("B", "A", 900, FRESH)]);
+        let snapshot = QuoteSnapshot::capture(&graph);
+        let index = CycleIndex::through_node(&snapshot.topology, snapshot.start_node, 3);
+        let from_bfs = search_snapshot(&snapshot, 3, Duration::from_secs(60));
+        let from_index = search_index(&snapshot, &index, Duration::from_secs(60));
+        let cycles = |result: &CachedSearchResult| {
+            let mut cycles: Vec<(Vec<usize>, i128)> = result.opportunities.iter()
+                .map(|opportunity| (opportunity.edge_ids(), opportunity.gross_profit))
+                .collect();
+            cycles.sort();
+            cycles
+        };
+        assert_eq!(from_bfs.opportunities.len(), 4);
+        assert_eq!(cycles(&from_bfs), cycles(&from_index));
+    }
+
+    #[test]
+    fn scale_leg_keeps_the_cached_rate() {
+        let leg = Leg::from_quote(0, &QuoteResponse::for_test("S", "A", 1000, 1500), SystemTime::now()).unwrap();
+        let scaled = scale_leg(&leg, 3000);
+        assert_eq!((scaled.in_amount, scaled.out_amount), (3000, 4500));
+        assert_eq!(scale_leg(&leg, 1000).out_amount, 1500);
+    }
+}
@@ -1 +1,7 @@
-pub mod search;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod search;
+pub mod opportunity;
+pub mod cached_search;
+pub mod negative_cycle;
+pub mod trade_size;
+pub mod ranking;
\ No newline at end of file
@@ -0,0 +1,283 @@
+// Arbitrage detection as negative cycle detection
+// Each usable edge gets the weight -ln(out_amount / in_amount) of its cached quote, so a cycle whose
+// product of exchange rates is greater than 1 is a cycle with negative total weight.
+// Bellman-Ford runs in O(V * E), and the layered search of the cycles through the start node in O(V^2 * E) at worst,
+// so unlike the BFS it doesn't blow up with the path length.
+
+use std::collections::HashSet;
+use std::time::Duration;
+use serde::Serialize;
+use crate::graph::static_graph::StaticGraph;
+use crate::search::cached_search::{estimate_cycle, usable_legs, QuoteSnapshot, SkippedEdge};
+use crate::search::opportunity::{Leg, Opportunity};
+
+// Relaxations smaller than this are treated as rounding noise
+const EPSILON: f64 = 1e-12;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct NegativeCycleResult {
+    pub opportunities: Vec<Opportunity>, // negative cycles through the start node, evaluated at the start amount
+    pub other_cycles: Vec<Vec<usize>>, // negative cycles found that don't pass through the start node, as edge ids
+    pub skipped_edges: Vec<SkippedEdge>,
+}
+
+pub fn search_negative_cycles(snapshot: &QuoteSnapshot, max_quote_age: Duration) -> NegativeCycleResult {
+    let graph = snapshot.topology.as_ref();
+    let start_node_id = snapshot.start_node;
+    let n_node = graph.head.len();
+    let (usable, skipped_edges) = usable_legs(snapshot, max_quote_age);
+
+    // (from, to, edge_idx, weight) of every usable edge
+    let mut edges: Vec<(usize, usize, usize, f64)> = vec![];
+    let csr = graph.frozen();
+    for from in 0..n_node {
+        for (edge_idx, to) in csr.out_edges(from) {
+            if let Some(cached_edge) = &usable[edge_idx] && cached_edge.leg.out_amount > 0 {
+                edges.push((from, to, edge_idx, edge_weight(&cached_edge.leg)));
+            }
+        }
+    }
+
+    // every edge on a start cycle has a usable leg, otherwise it wouldn't have a weight
+    let opportunities = start_cycles(graph, start_node_id, &edges).iter()
+        .filter_map(|cycle| estimate_cycle(&usable, cycle, snapshot.start_amount))
+        .collect();
+    let other_cycles = all_cycles(graph, start_node_id, &edges).into_iter()
+        .filter(|cycle| cycle.iter().all(|&e| graph.edge(e).0 != start_node_id))
+        .collect();
+
+    NegativeCycleResult {
+        opportunities,
+        other_cycles,
+        skipped_edges,
+    }
+}
+
+// The negative cycles through the start node, each starting with an edge out of it
+// The start node is split into a source with only its out edges and a sink with only its in edges, and layer k
+// holds the lightest path of k edges from the source to every node that doesn't repeat a node. So a negative cycle
+// elsewhere can't take over the paths as it does in a plain Bellman-Ford, where it hides the cycles through the start.
+// Only the lightest path to each node is extended, so a cycle whose prefix isn't the lightest one can be missed.
+fn start_cycles(graph: &StaticGraph, start_node_id: usize, edges: &[(usize, usize, usize, f64)]) -> Vec<Vec<usize>> {
+    let n_node = graph.head.len();
+    let mut cycles = vec![];
+    let mut dist = vec![f64::INFINITY; n_node];
+    dist[start_node_id] = 0.0;
+    // pred[k][v] is the last edge of the lightest path of k edges to v, pred[0] is the source alone
+    let mut pred: Vec<Vec<Option<usize>>> = vec![vec![None; n_node]];
+    // a simple cycle visits every node at most once
+    for _ in 1..n_node {
+        let mut next_dist = vec![f64::INFINITY; n_node];
+        let mut next_pred = vec![None; n_node];
+        for &(from, to, edge_idx, weight) in edges {
+            if !dist[from].is_finite() {
+                continue;
+            }
+            let total = dist[from] + weight;
+            if to == start_node_id {
+                // an edge into the sink closes a cycle
+                if total < -EPSILON {
+                    let mut cycle = path_edges(graph, &pred, from);
+                    cycle.push(edge_idx);
+                    cycles.push(cycle);
+                }
+            } else if total < next_dist[to] - EPSILON && !path_visits(graph, &pred, from, to) {
+                next_dist[to] = total;
+                next_pred[to] = Some(edge_idx);
+            }
+        }
+        if next_dist.iter().all(|d| !d.is_finite()) {
+            break;
+        }
+        dist = next_dist;
+        pred.push(next_pred);
+    }
+    cycles
+}
+
+// The edges of the lightest path of the last layer of pred to `node`, from the source
+fn path_edges(graph: &StaticGraph, pred: &[Vec<Option<usize>>], node: usize) -> Vec<usize> {
+    let mut edges = vec![];
+    let mut current = node;
+    for layer in pred[1..].iter().rev() {
+        let Some(edge_idx) = layer[current] else { break };
+        edges.push(edge_idx);
+        current = graph.edge(edge_idx).0;
+    }
+    edges.reverse();
+    edges
+}
+
+// Whether the lightest path of the last layer of pred to `node` goes through `target`, `node` included
+fn path_visits(graph: &StaticGraph, pred: &[Vec<Option<usize>>], node: usize, target: usize) -> bool {
+    let mut current = node;
+    for layer in pred[1..].iter().rev() {
+        if current == target {
+            return true;
+        }
+        let Some(edge_idx) = layer[current] else { break };
+        current = graph.edge(edge_idx).0;
+    }
+    current == target
+}
+
+// The negative cycles a Bellman-Ford from the start node runs into, as edge ids, each cycle once
+// The predecessors of a node lead to at most one cycle, so this is only a sample of the negative cycles
+fn all_cycles(graph: &StaticGraph, start_node_id: usize, edges: &[(usize, usize, usize, f64)]) -> Vec<Vec<usize>> {
+    let n_node = graph.head.len();
+    let mut dist = vec![f64::INFINITY; n_node];
+    let mut pred: Vec<Option<usize>> = vec![None; n_node]; // pred[v] is the last edge of the shortest path to v
+    dist[start_node_id] = 0.0;
+    for _ in 1..n_node {
+        let mut relaxed = false;
+        for &(from, to, edge_idx, weight) in edges {
+            if dist[from].is_finite() && dist[from] + weight < dist[to] - EPSILON {
+                dist[to] = dist[from] + weight;
+                pred[to] = Some(edge_idx);
+                relaxed = true;
+            }
+        }
+        if !relaxed {
+            break;
+        }
+    }
+
+    // Any edge that can still be relaxed leads back to a negative cycle through the predecessors
+    let mut seen: HashSet<Vec<usize>> = HashSet::new();
+    let mut cycles = vec![];
+    for &(from, to, edge_idx, weight) in edges {
+        if !(dist[from].is_finite() && dist[from] + weight < dist[to] - EPSILON) {
+            continue;
+        }
+        dist[to] = dist[from] + weight;
+        pred[to] = Some(edge_idx);
+
+        let Some(cycle) = trace_cycle(to, graph, &pred) else {
+            continue;
+        };
+        if seen.insert(canonical_rotation(&cycle)) {
+            cycles.push(cycle);
+        }
+    }
+    cycles
+}
+
+fn edge_weight(leg: &Leg) -> f64 {
+    -((leg.out_amount as f64).ln() - (leg.in_amount as f64).ln())
+}
+
+// Walk back as many predecessors as there are nodes from `node` to land on the cycle, then collect the cycle's edges in order
+fn trace_cycle(node: usize, graph: &StaticGraph, pred: &[Option<usize>]) -> Option<Vec<usize>> {
+    let mut on_cycle = node;
+    for _ in 0..graph.head.len() {
+        on_cycle = graph.edge(pred[on_cycle]?).0;
+    }
+    let mut cycle = vec![];
+    let mut current = on_cycle;
+    loop {
+        let edge_idx = pred[current]?;
+        cycle.push(edge_idx);
+        current = graph.edge(edge_idx).0;
+        if current == on_cycle {
+            break;
+        }
+    }
+    cycle.reverse();
+    Some(cycle)
+}
+
+// Rotate the cycle so that its smallest edge id comes first, used to deduplicate cycles
+fn canonical_rotation(cycle: &[usize]) -> Vec<usize> {
+    let pos = cycle.iter().enumerate().min_by_key(|(_, e)| **e).map_or(0, |(i, _)| i);
+    cycle[pos..].iter().chain(cycle[..pos].iter()).copied().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Instant;
+    use super::*;
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::dynamic_graph::{DynamicGraph, EdgeAttribute};
+    use crate::graph::static_graph::DexFilter;
+    use crate::jupiter::quote::QuoteResponse;
+
+    // S <-> A <-> B <-> S and B <-> C, every edge quoted at 1000 with the out amount of `rates`, 900 for the others
+    fn snapshot(rates: &[(&str, &str, u64)]) -> QuoteSnapshot {
+        let static_graph = GraphBuilder::new()
+            .test_nodes(&[("S", Some(6)), ("A", Some(6)), ("B", Some(6)), ("C", Some(6))])
+            .mint_pair("S", "A")
+            .mint_pair("A", "B")
+            .mint_pair("B", "S")
+            .mint_pair("B", "C")
+            .build()
+            .unwrap();
+        let start_node = static_graph.node_id("S").unwrap();
+        let graph = DynamicGraph::new(Arc::new(static_graph), start_node, 1000);
+        let topology = graph.topology();
+        let updates = (0..topology.n_edge()).map(|edge_idx| {
+            let (input_mint, output_mint) = (topology.input_mint(edge_idx), topology.output_mint(edge_idx));
+            let out_amount = rates.iter()
+                .find(|&&(from, to, _)| from == input_mint && to == output_mint)
+                .map_or(900, |&(_, _, out_amount)| out_amount);
+            (edge_idx, EdgeAttribute {
+                quote_response: Some(QuoteResponse::for_test(input_mint, output_mint, 1000, out_amount)),
+                last_updated: Instant::now(),
+                ..EdgeAttribute::new()
+            })
+        }).collect();
+        graph.attrs.set_many(&topology, updates);
+        QuoteSnapshot::capture(&graph)
+    }
+
+    fn edges(snapshot: &QuoteSnapshot, path: &[&str]) -> Vec<usize> {
+        path.windows(2).map(|pair| snapshot.topology.find_edge(pair[0], pair[1], &DexFilter::default()).unwrap()).collect()
+    }
+
+    #[test]
+    fn finds_the_start_cycle() {
+        let snapshot = snapshot(&[("S", "A", 1040), ("A", "B", 1000), ("B", "S", 1000)]);
+        let result = search_negative_cycles(&snapshot, Duration::from_secs(60));
+        assert_eq!(result.opportunities.len(), 1);
+        let opportunity = &result.opportunities[0];
+        assert_eq!(opportunity.edge_ids(), edges(&snapshot, &["S", "A", "B", "S"]));
+        assert_eq!(opportunity.gross_profit, 40);
+        assert!(result.other_cycles.is_empty());
+    }
+
+    #[test]
+    fn a_cycle_elsewhere_does_not_hide_the_start_cycle() {
+        let snapshot = snapshot(&[("S", "A", 1040), ("A", "B", 1000), ("B", "S", 1000), ("B", "C", 1200)]);
+        let result = search_negative_cycles(&snapshot, Duration::from_secs(60));
+        assert_eq!(result.opportunities.len(), 1);
+        assert_eq!(result.opportunities[0].edge_ids(), edges(&snapshot, &["S", "A", "B", "S"]));
+        // the B <-> C loop is reported on its own
+        let loop_edges = edges(&snapshot, &["B", "C", "B"]);
+        assert_eq!(result.other_cycles.len(), 1);
+        assert_eq!(canonical_rotation(&result.other_cycles[0]), canonical_rotation(&loop_edges));
+    }
+
+    #[test]
+    fn finds_nothing_without_a_profitable_cycle() {
+        let result = search_negative_cycles(&snapshot(&[]), Duration::from_secs(60));
+        assert!(result.opportunities.is_empty());
+        assert!(result.other_cycles.is_empty());
+    }
+
+    #[test]
+    fn trace_cycle_walks_back_to_the_cycle() {
+        let snapshot = snapshot(&[]);
+        let graph = snapshot.topology.as_ref();
+        let [s_a, a_b, b_c, c_b] = [("S", "A"), ("A", "B"), ("B", "C"), ("C", "B")]
+            .map(|(from, to)| graph.find_edge(from, to, &DexFilter::default()).unwrap());
+        let mut pred = vec![None; graph.head.len()];
+        pred[graph.node_id("A").unwrap()] = Some(s_a);
+        pred[graph.node_id("B").unwrap()] = Some(c_b);
+        pred[graph.node_id("C").unwrap()] = Some(b_c);
+        let cycle = trace_cycle(graph.node_id("C").unwrap(), graph, &pred).unwrap();
+        assert_eq!(canonical_rotation(&cycle), canonical_rotation(&[b_c, c_b]));
+        // S has no predecessor, the walk back from A ends before reaching a cycle
+        pred[graph.node_id("B").unwrap()] = Some(a_b);
+        assert!(trace_cycle(graph.node_id("C").unwrap(), graph, &pred).is_none());
+    }
+}
@@ -0,0 +1,169 @@
+// The result type of a search: a cycle that starts and ends at the start node, with its quoted legs
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use crate::graph::static_graph::StaticGraph;
+use crate::jupiter::quote::QuoteResponse;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Leg {
+    pub edge_id: usize, // the id of the edge in the StaticGraph
+    pub input_mint: String,
+    pub output_mint: String,
+    pub in_amount: u64,
+    pub out_amount: u64,
+    pub amm_labels: Vec<String>, // the labels of the AMMs in the route plan, in order
+    pub price_impact_pct: String,
+    pub context_slot: Option<i64>,
+    pub quoted_at_ms: u64, // the wall-clock time the quote was received, in milliseconds since the unix epoch
+}
+
+impl Leg {
+    // Build a leg from a quote response, returns None if the amounts in the response can't be parsed
+    pub fn from_quote(edge_id: usize, quote_rsp: &QuoteResponse, quoted_at: SystemTime) -> Option<Self> {
+        let in_amount = match quote_rsp.in_amount.parse::<u64>() {
+            Ok(amount) => amount,
+            Err(e) => {
+                eprintln!("Invalid in amount {}: {}", quote_rsp.in_amount, e);
+                return None;
+            }
+        };
+        let out_amount = match quote_rsp.out_amount.parse::<u64>() {
+            Ok(amount) => amount,
+            Err(e) => {
+                eprintln!("Invalid out amount {}: {}", quote_rsp.out_amount, e);
+                return None;
+            }
+        };
+        Some(Self {
+            edge_id,
+            input_mint: quote_rsp.input_mint.clone(),
+            output_mint: quote_rsp.output_mint.clone(),
+            in_amount,
+            out_amount,
+            amm_labels: quote_rsp.route_plan.iter().map(|plan| plan.swap_info.label.clone()).collect(),
+            price_impact_pct: quote_rsp.price_impact_pct.clone(),
+            context_slot: quote_rsp.context_slot,
+            quoted_at_ms: unix_millis(quoted_at),
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Opportunity {
+    pub legs: Vec<Leg>, // the legs of the cycle, in trading order
+    pub start_amount: u64, // the in amount of the first leg
+    pub end_amount: u64, // the out amount of the last leg
+    pub gross_profit: i128, // end_amount - start_amount, in the smallest unit of the start token, before any fee
+    pub roi_bps: f64, // gross_profit / start_amount, in basis points
+    pub first_quoted_at_ms: u64,
+    pub last_quoted_at_ms: u64,
+}
+
+impl Opportunity {
+    // The legs should be non-empty and chained, i.e. each leg's output mint is the next leg's input mint
+    pub fn new(legs: Vec<Leg>) -> Self {
+        let start_amount = legs.first().map_or(0, |leg| leg.in_amount);
+        let end_amount = legs.last().map_or(0, |leg| leg.out_amount);
+        let gross_profit = end_amount as i128 - start_amount as i128;
+        let roi_bps = if start_amount == 0 {
+            0.0
+        } else {
+            gross_profit as f64 * 10_000.0 / start_amount as f64
+        };
+        let first_quoted_at_ms = legs.iter().map(|leg| leg.quoted_at_ms).min().unwrap_or(0);
+        let last_quoted_at_ms = legs.iter().map(|leg| leg.quoted_at_ms).max().unwrap_or(0);
+        Self {
+            legs,
+            start_amount,
+            end_amount,
+            gross_profit,
+            roi_bps,
+            first_quoted_at_ms,
+            last_quoted_at_ms,
+        }
+    }
+
+    // Profitable before fees, see ranking.rs for the profit net of estimated fees
+    pub fn is_profitable(&self) -> bool {
+        self.gross_profit > 0
+    }
+
+    // The edge ids of the cycle, in trading order
+    pub fn edge_ids(&self) -> Vec<usize> {
+        self.legs.iter().map(|leg| leg.edge_id).collect()
+    }
+
+    // The symbols of the tokens along the cycle, starting and ending with the start token
+    pub fn symbols<'a>(&self, graph: &'a StaticGraph) -> Vec<&'a str> {
+        let Some(first) = self.legs.first() else {
+            return vec![];
+        };
+        std::iter::once(graph.edge_info[first.edge_id].input_node)
+            .chain(self.legs.iter().map(|leg| graph.edge_info[leg.edge_id].output_node))
+            .map(|node| graph.node(node).symbol.as_str())
+            .collect()
+    }
+}
+
+pub(crate) fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::static_graph::DexFilter;
+
+    fn leg(graph: &StaticGraph, from: &str, to: &str, in_amount: u64, out_amount: u64, quoted_at_ms: u64) -> Leg {
+        let edge_idx = graph.find_edge(from, to, &DexFilter::default()).unwrap();
+        let quoted_at = UNIX_EPOCH + Duration::from_millis(quoted_at_ms);
+        Leg::from_quote(edge_idx, &QuoteResponse::for_test(from, to, in_amount, out_amount), quoted_at).unwrap()
+    }
+
+    fn graph() -> StaticGraph {
+        GraphBuilder::new()
+            .test_nodes(&[("S", Some(6)), ("A", Some(6)), ("B", Some(6))])
+            .mint_pair("S", "A")
+            .mint_pair("A", "B")
+            .mint_pair("B", "S")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn profit_and_times_come_from_the_first_and_last_legs() {
+        let graph = graph();
+        let opportunity = Opportunity::new(vec![
+            leg(&graph, "S", "A", 1_000, 2_000, 30),
+            leg(&graph, "A", "B", 2_000, 500, 10),
+            leg(&graph, "B", "S", 500, 1_050, 20),
+        ]);
+        assert_eq!((opportunity.start_amount, opportunity.end_amount), (1_000, 1_050));
+        assert_eq!(opportunity.gross_profit, 50);
+        assert_eq!(opportunity.roi_bps, 500.0);
+        assert!(opportunity.is_profitable());
+        assert_eq!((opportunity.first_quoted_at_ms, opportunity.last_quoted_at_ms), (10, 30));
+        assert_eq!(opportunity.symbols(&graph), ["S", "A", "B", "S"]);
+        assert_eq!(opportunity.edge_ids(), opportunity.legs.iter().map(|leg| leg.edge_id).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn a_loss_is_a_negative_profit() {
+        let graph = graph();
+        let opportunity = Opportunity::new(vec![leg(&graph, "S", "A", 1_000, 900, 0), leg(&graph, "A", "S", 900, 990, 0)]);
+        assert_eq!(opportunity.gross_profit, -10);
+        assert_eq!(opportunity.roi_bps, -100.0);
+        assert!(!opportunity.is_profitable());
+    }
+
+    #[test]
+    fn an_unparsable_amount_gives_no_leg() {
+        let mut quote = QuoteResponse::for_test("S", "A", 1_000, 2_000);
+        quote.out_amount = "not a number".to_string();
+        assert!(Leg::from_quote(0, &quote, SystemTime::now()).is_none());
+        assert!(Opportunity::new(vec![]).symbols(&graph()).is_empty());
+    }
+}
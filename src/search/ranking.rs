@@ -0,0 +1,108 @@
+// Rank the opportunities of a search and keep the best K under a chosen objective
+
+use crate::search::opportunity::Opportunity;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Objective {
+    AbsoluteProfit, // gross profit in the smallest unit of the start token
+    RoiBps, // gross profit relative to the start amount
+    NetProfit { fee_per_tx: u64, fee_per_hop: u64 }, // gross profit minus fee_per_tx + fee_per_hop * number of legs
+    ProfitPerHop, // gross profit divided by the number of legs, shorter cycles are less likely to move before execution
+}
+
+impl Objective {
+    pub fn score(&self, opportunity: &Opportunity) -> f64 {
+        match self {
+            Objective::AbsoluteProfit => opportunity.gross_profit as f64,
+            Objective::RoiBps => opportunity.roi_bps,
+            Objective::NetProfit { .. } => self.net_profit(opportunity) as f64,
+            Objective::ProfitPerHop => opportunity.gross_profit as f64 / opportunity.legs.len().max(1) as f64,
+        }
+    }
+
+    // The estimated cost of executing the opportunity, only the NetProfit objective counts fees
+    // Saturates instead of overflowing, a fee that large rules the opportunity out anyway
+    pub fn fee_estimate(&self, opportunity: &Opportunity) -> u64 {
+        match self {
+            Objective::NetProfit { fee_per_tx, fee_per_hop } => fee_per_hop.saturating_mul(opportunity.legs.len() as u64).saturating_add(*fee_per_tx),
+            _ => 0,
+        }
+    }
+
+    // The gross profit minus the fee estimate of this objective
+    pub fn net_profit(&self, opportunity: &Opportunity) -> i128 {
+        opportunity.gross_profit - self.fee_estimate(opportunity) as i128
+    }
+}
+
+// Keep the k best opportunities under the objective whose net profit is at least min_profit, best first
+// Only the NetProfit objective subtracts fees, with the other objectives net profit is the gross profit
+// Ties keep the order of the input, so the ranking is deterministic
+pub fn top_k(opportunities: Vec<Opportunity>, objective: Objective, k: usize, min_profit: i128) -> Vec<Opportunity> {
+    let mut ranked: Vec<(f64, Opportunity)> = opportunities.into_iter()
+        .filter(|opportunity| objective.net_profit(opportunity) >= min_profit)
+        .map(|opportunity| (objective.score(&opportunity), opportunity))
+        .collect();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+    ranked.truncate(k);
+    ranked.into_iter().map(|(_, opportunity)| opportunity).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+    use super::*;
+    use crate::jupiter::quote::QuoteResponse;
+    use crate::search::opportunity::Leg;
+
+    // A cycle of `hops` legs from start_amount to end_amount, the legs in between break even
+    fn opportunity(start_amount: u64, end_amount: u64, hops: usize) -> Opportunity {
+        let legs = (0..hops).map(|hop| {
+            let out_amount = if hop + 1 == hops { end_amount } else { start_amount };
+            Leg::from_quote(hop, &QuoteResponse::for_test("S", "A", start_amount, out_amount), SystemTime::now()).unwrap()
+        }).collect();
+        Opportunity::new(legs)
+    }
+
+    fn profits(opportunities: &[Opportunity]) -> Vec<i128> {
+        opportunities.iter().map(|opportunity| opportunity.gross_profit).collect()
+    }
+
+    #[test]
+    fn each_objective_orders_the_opportunities() {
+        // +450 on 10000 in 3 hops, +200 on 1000 in 2 hops, +120 on 1000 in 1 hop
+        let all = || vec![opportunity(10_000, 10_450, 3), opportunity(1_000, 1_200, 2), opportunity(1_000, 1_120, 1)];
+        assert_eq!(profits(&top_k(all(), Objective::AbsoluteProfit, 3, 0)), [450, 200, 120]);
+        assert_eq!(profits(&top_k(all(), Objective::RoiBps, 3, 0)), [200, 120, 450]);
+        assert_eq!(profits(&top_k(all(), Objective::ProfitPerHop, 3, 0)), [450, 120, 200]);
+        // net of 50 per transaction and 40 per hop: 280, 70 and 30
+        let net = Objective::NetProfit { fee_per_tx: 50, fee_per_hop: 40 };
+        let ranked = top_k(all(), net, 3, 0);
+        assert_eq!(ranked.iter().map(|o| net.net_profit(o)).collect::<Vec<_>>(), [280, 70, 30]);
+    }
+
+    #[test]
+    fn keeps_k_opportunities_above_the_minimum_profit() {
+        let all = vec![opportunity(1_000, 900, 2), opportunity(1_000, 1_050, 2), opportunity(1_000, 1_200, 2)];
+        assert_eq!(profits(&top_k(all.clone(), Objective::AbsoluteProfit, 1, 0)), [200]);
+        assert_eq!(profits(&top_k(all.clone(), Objective::AbsoluteProfit, 5, 1)), [200, 50]);
+        // the fees count against the minimum profit, only with NetProfit
+        let net = Objective::NetProfit { fee_per_tx: 0, fee_per_hop: 30 };
+        assert_eq!(profits(&top_k(all, net, 5, 1)), [200]);
+    }
+
+    #[test]
+    fn ties_keep_the_input_order() {
+        let all = vec![opportunity(1_000, 1_100, 2), opportunity(2_000, 2_100, 3), opportunity(500, 600, 1)];
+        let ranked = top_k(all, Objective::AbsoluteProfit, 3, 0);
+        assert_eq!(ranked.iter().map(|o| o.start_amount).collect::<Vec<_>>(), [1_000, 2_000, 500]);
+    }
+
+    #[test]
+    fn a_huge_fee_saturates() {
+        let net = Objective::NetProfit { fee_per_tx: u64::MAX, fee_per_hop: u64::MAX };
+        let huge = opportunity(1_000, 1_100, 3);
+        assert_eq!(net.fee_estimate(&huge), u64::MAX);
+        assert!(top_k(vec![huge], net, 1, 0).is_empty());
+    }
+}
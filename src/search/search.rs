@@ -1,70 +1,24 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::sync::Arc;
-use tokio::time::Instant;
-use crate::graph::{dynamic_graph, static_graph};
-use crate::graph::static_graph::{EdgeInfo, StaticGraph};
-use crate::jupiter::quote::{quote, QuoteParams, QuoteResponse};
+use std::time::{Instant, SystemTime};
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use crate::graph::builder::GraphBuilder;
+use crate::graph::pruning::StartCyclePruning;
+use crate::graph::static_graph::StaticGraph;
+use crate::jupiter::quote::{quote_jupiter, QuoteParams, QuoteResponse};
+use crate::search::opportunity::{Leg, Opportunity};
 use crate::mints::mints;
 use std::collections::VecDeque;
 
-const JUPITER_URL: &str = "http://64.130.36.228:18080";
 pub fn create_static_graph() -> StaticGraph {
-    // When initializing the static graph, we need to know the number of nodes
-    let mut static_graph = static_graph::StaticGraph::new(6);
-
-    // And we also need to assign the node id (from 1 to n_node) to the mint address
-    let mut token2id = HashMap::new();
-    let mut id2token = HashMap::new();
-    token2id.insert(mints::WSOL.mint, 1);
-    id2token.insert(1, mints::WSOL.mint);
-    token2id.insert(mints::USDC.mint, 2);
-    id2token.insert(2, mints::USDC.mint);
-    token2id.insert(mints::USDT.mint, 3);
-    id2token.insert(3, mints::USDT.mint);
-    token2id.insert(mints::WETH.mint, 4);
-    id2token.insert(4, mints::WETH.mint);
-    token2id.insert(mints::FARTCOIN.mint, 5);
-    id2token.insert(5, mints::FARTCOIN.mint);
-    token2id.insert(mints::POPCAT.mint, 6);
-    id2token.insert(6, mints::POPCAT.mint);
-
-    // Add edges to the graph
-    // WSOL <-> USDC
-    static_graph.add_edge(token2id[&mints::WSOL.mint], token2id[&mints::USDC.mint], mints::WSOL.mint.to_string(), mints::USDC.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDC.mint], token2id[&mints::WSOL.mint], mints::USDC.mint.to_string(), mints::WSOL.mint.to_string());
-    // WSOL <-> USDT
-    static_graph.add_edge(token2id[&mints::WSOL.mint], token2id[&mints::USDT.mint], mints::WSOL.mint.to_string(), mints::USDT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDT.mint], token2id[&mints::WSOL.mint], mints::USDT.mint.to_string(), mints::WSOL.mint.to_string());
-    // USDC <-> USDT
-    static_graph.add_edge(token2id[&mints::USDC.mint], token2id[&mints::USDT.mint], mints::USDC.mint.to_string(), mints::USDT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDT.mint], token2id[&mints::USDC.mint], mints::USDT.mint.to_string(), mints::USDC.mint.to_string());
-
-    // SOL <-> WETH, USDC <-> WETH, USDT <-> WETH
-    static_graph.add_edge(token2id[&mints::USDC.mint], token2id[&mints::WETH.mint], mints::USDC.mint.to_string(), mints::WETH.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WETH.mint], token2id[&mints::USDC.mint], mints::WETH.mint.to_string(), mints::USDC.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDT.mint], token2id[&mints::WETH.mint], mints::USDT.mint.to_string(), mints::WETH.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WETH.mint], token2id[&mints::USDT.mint], mints::WETH.mint.to_string(), mints::USDT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WSOL.mint], token2id[&mints::WETH.mint], mints::WSOL.mint.to_string(), mints::WETH.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WETH.mint], token2id[&mints::WSOL.mint], mints::WETH.mint.to_string(), mints::WSOL.mint.to_string());
-
-    // SOL <-> FARTCOIN, USDC <-> FARTCOIN, USDT <-> FARTCOIN
-    static_graph.add_edge(token2id[&mints::USDC.mint], token2id[&mints::FARTCOIN.mint], mints::USDC.mint.to_string(), mints::FARTCOIN.mint.to_string());
-    static_graph.add_edge(token2id[&mints::FARTCOIN.mint], token2id[&mints::USDC.mint], mints::FARTCOIN.mint.to_string(), mints::USDC.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDT.mint], token2id[&mints::FARTCOIN.mint], mints::USDT.mint.to_string(), mints::FARTCOIN.mint.to_string());
-    static_graph.add_edge(token2id[&mints::FARTCOIN.mint], token2id[&mints::USDT.mint], mints::FARTCOIN.mint.to_string(), mints::USDT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WSOL.mint], token2id[&mints::FARTCOIN.mint], mints::WSOL.mint.to_string(), mints::FARTCOIN.mint.to_string());
-    static_graph.add_edge(token2id[&mints::FARTCOIN.mint], token2id[&mints::WSOL.mint], mints::FARTCOIN.mint.to_string(), mints::WSOL.mint.to_string());
-
-    // SOL <-> POPCAT, USDC <-> POPCAT, USDT <-> POPCAT
-    static_graph.add_edge(token2id[&mints::USDC.mint], token2id[&mints::POPCAT.mint], mints::USDC.mint.to_string(), mints::POPCAT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::POPCAT.mint], token2id[&mints::USDC.mint], mints::POPCAT.mint.to_string(), mints::USDC.mint.to_string());
-    static_graph.add_edge(token2id[&mints::USDT.mint], token2id[&mints::POPCAT.mint], mints::USDT.mint.to_string(), mints::POPCAT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::POPCAT.mint], token2id[&mints::USDT.mint], mints::POPCAT.mint.to_string(), mints::USDT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::WSOL.mint], token2id[&mints::POPCAT.mint], mints::WSOL.mint.to_string(), mints::POPCAT.mint.to_string());
-    static_graph.add_edge(token2id[&mints::POPCAT.mint], token2id[&mints::WSOL.mint], mints::POPCAT.mint.to_string(), mints::WSOL.mint.to_string());
-
-
-    static_graph
+    GraphBuilder::new()
+        .tokens(&mints::DEFAULT_TOKENS)
+        .pairs(&mints::DEFAULT_PAIRS)
+        .build()
+        .expect("The default tokens and pairs form a valid graph")
 }
 
 #[derive(Clone, Debug)]
@@ -73,8 +27,8 @@ pub struct BFSStatus {
     pub visited: Vec<bool>, // visited[i] = true if node_i has been visited
     pub path: Vec<usize>, // the i-th edge in the path is path[i]
     pub path_tail: usize, // path_tail = the tail of the path Vec
-    pub quote_response_map: HashMap<String, QuoteResponse>, // quote_response_map[mint1_mint2] = the quote response of edge mint1 -> mint2
-    pub first_rsp_time: Option<Instant> // the first response time of this staus
+    pub legs: Vec<Arc<Leg>>, // legs[i] is the quoted leg of edge path[i], shared with the quote cache of the search
+    pub current_amount: u64, // the out amount of the last edge in the path, which is the in amount of the next edge
 }
 
 
@@ -85,131 +39,358 @@ impl BFSStatus {
             visited: vec![false; n_node + 1],
             path: vec![0; max_path_len + 1],
             path_tail: 0,
-            quote_response_map: HashMap::new(),
-            first_rsp_time: None,
+            legs: Vec::with_capacity(max_path_len),
+            current_amount: 0,
         }
     }
 }
 
 
-pub async fn search(graph: StaticGraph, start_node_id: usize, start_amount: u64, max_path_len: usize) -> Option<Vec<BFSStatus>> {
-    let mut queue: VecDeque<BFSStatus> = VecDeque::new();
-    let mut edge_idx = match graph.head[start_node_id] {
-        Some(idx) => idx,
-        None => {
-            eprintln!("No edge from start node {}", start_node_id);
-            return None;
-        },
-    };
-
-
-    loop {
-        let to_node = graph.to[edge_idx];
-        let edge_info = &graph.edge_info[edge_idx];
-        let input_mint = &edge_info.input_mint;
-        let output_mint = &edge_info.output_mint;
-        let mut status = BFSStatus::new(graph.head.len(), max_path_len);
-        // status.visited[start_node_id] = true;
-        if !status.visited[to_node] {
-            status.current_edge_id = edge_idx;
-            status.visited[to_node] = true;
-            status.path[0] = edge_idx;
-            status.path_tail += 1;
-            
-            
-            let quote_params = QuoteParams::new(
-                input_mint.clone(),
-                output_mint.clone(),
-                start_amount,
-            );
-            let quote_rsp: Option<QuoteResponse> = match quote(JUPITER_URL, quote_params).await {
-                Ok(response) => {
-                    Some(response)
+// Quote a single edge and turn the response into a leg
+async fn quote_leg<F, Fut>(edge_idx: usize, quote_params: QuoteParams, quote_fn: F) -> Option<Leg>
+where
+    F: Fn(QuoteParams) -> Fut,
+    Fut: Future<Output = Result<QuoteResponse, Box<dyn std::error::Error + Send>>>,
+{
+    match quote_fn(quote_params).await {
+        Ok(response) => Leg::from_quote(edge_idx, &response, SystemTime::now()),
+        Err(e) => {
+            eprintln!("Quote Error: {}", e);
+            None
+        }
+    }
+}
+
+// When a search has to stop before the queue is empty
+pub struct SearchLimits {
+    pub deadline: Option<Instant>,
+    pub cancel: Option<watch::Receiver<bool>>, // the search stops once true is sent
+}
+
+impl SearchLimits {
+    // No limit, the search runs until every path up to max_path_len is explored
+    pub fn none() -> Self {
+        Self {
+            deadline: None,
+            cancel: None,
+        }
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_cancel(mut self, cancel: watch::Receiver<bool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    // Whether a limit has already been hit, without waiting
+    fn check(&self) -> Option<SearchCompletion> {
+        if self.cancel.as_ref().is_some_and(|cancel| *cancel.borrow()) {
+            return Some(SearchCompletion::Cancelled);
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Some(SearchCompletion::DeadlineReached);
+        }
+        None
+    }
+
+    // Wait until a limit is hit, never returns if there is no limit
+    async fn stopped(&mut self) -> SearchCompletion {
+        let deadline = self.deadline;
+        let wait_deadline = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending().await,
+            }
+        };
+        let wait_cancel = async {
+            match self.cancel.as_mut() {
+                // a dropped sender can't cancel anymore
+                Some(cancel) => if cancel.wait_for(|&cancelled| cancelled).await.is_err() {
+                    std::future::pending::<()>().await
                 },
-                Err(e) => {
-                    eprintln!("Quote Error: {}", e);
-                    None
-                }
-            };
-            
-            if let Some(quote_rsp) = quote_rsp {
-                status.quote_response_map.insert(format!("{}_{}", input_mint, output_mint), quote_rsp);
-                status.first_rsp_time = Some(Instant::now());
-                
-                if status.path_tail < max_path_len {
-                    queue.push_back(status);
-                } else {
-                    eprintln!("Path length exceeds the maximum length");
-                }
+                None => std::future::pending().await,
             }
-        }
-        
-        match graph.next[edge_idx] {
-            Some(idx) => edge_idx = idx,
-            None => break,
+        };
+        tokio::select! {
+            _ = wait_deadline => SearchCompletion::DeadlineReached,
+            _ = wait_cancel => SearchCompletion::Cancelled,
         }
     }
-    
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchCompletion {
+    Finished, // every path up to max_path_len was explored
+    DeadlineReached,
+    Cancelled,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchOutcome {
+    pub opportunities: Vec<Opportunity>, // the opportunities found before the search stopped
+    pub completion: SearchCompletion,
+    pub levels_completed: usize, // the number of BFS levels fully expanded, i.e. all cycles up to this length were quoted
+}
+
+pub async fn search(graph: &StaticGraph, start_node_id: usize, start_amount: u64, max_path_len: usize, max_concurrency: usize)
+    -> Option<Vec<Opportunity>> {
+    search_until(graph, start_node_id, start_amount, max_path_len, max_concurrency, SearchLimits::none(), quote_jupiter).await
+        .map(|outcome| outcome.opportunities)
+}
+
+// The BFS is expanded one level at a time, and all quotes of a level are requested concurrently
+// with at most max_concurrency requests in flight. The results are applied in the order a sequential
+// BFS would visit them, so the opportunities and their order don't depend on the response timing.
+// When a limit is hit, the quotes still in flight are dropped and the quotes already received are applied.
+// Each (edge, amount) is quoted with quote_fn, quote_jupiter outside of the tests.
+pub async fn search_until<F, Fut>(graph: &StaticGraph, start_node_id: usize, start_amount: u64, max_path_len: usize,
+                                  max_concurrency: usize, mut limits: SearchLimits, quote_fn: F) -> Option<SearchOutcome>
+where
+    F: Fn(QuoteParams) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<QuoteResponse, Box<dyn std::error::Error + Send>>> + Send + 'static,
+{
+    if graph.out_edges(start_node_id).next().is_none() {
+        eprintln!("No edge from start node {}", start_node_id);
+        return None;
+    }
+
+    // edges that can't close a cycle within max_path_len are never quoted
+    let pruning = StartCyclePruning::analyze(graph, start_node_id, max_path_len);
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(max_concurrency.max(1)));
     let mut opportunities = vec![];
-    // check while the queue is not empty
-    while let Some(status) = queue.pop_front() {
-        eprintln!("queue len: {}", queue.len());
-        let current_to_node_id = graph.to[status.current_edge_id];
-        if current_to_node_id == start_node_id {
-            opportunities.push(status);
-            continue;
+    let mut root = BFSStatus::new(graph.head.len(), max_path_len);
+    root.current_amount = start_amount;
+    let mut level: VecDeque<BFSStatus> = VecDeque::from([root]);
+    // quote_cache[(edge, amount)] is the quoted leg, None if the quote failed, shared by all branches of the search
+    let mut quote_cache: HashMap<(usize, u64), Option<Arc<Leg>>> = HashMap::new();
+    let mut cache_hits = 0;
+    let mut completion = SearchCompletion::Finished;
+    let mut levels_completed = 0;
+
+    while !level.is_empty() {
+        if let Some(stop) = limits.check() {
+            completion = stop;
+            break;
         }
-        
-        let mut edge_idx = match graph.head[current_to_node_id] {
-            Some(idx) => idx,
-            None => {
-                continue;
-            },
-        };
-        loop {
-            let to_node = graph.to[edge_idx];
-            let edge_info = &graph.edge_info[edge_idx];
-            let input_mint = &edge_info.input_mint;
-            let output_mint = &edge_info.output_mint;
-            let mut new_status = status.clone();
-            
-            if !new_status.visited[to_node] {
-                new_status.current_edge_id = edge_idx;
-                new_status.visited[to_node] = true;
-                new_status.path[new_status.path_tail] = edge_idx;
-                new_status.path_tail += 1;
-
-                let quote_params = QuoteParams::new(
-                    input_mint.clone(),
-                    output_mint.clone(),
-                    start_amount,
-                );
-                //println!("Quote params: {:?}", quote_params);
-                let quote_rsp: Option<QuoteResponse> = match quote(JUPITER_URL, quote_params).await {
-                    Ok(response) => {
-                        Some(response)
-                    },
-                    Err(e) => {
-                        eprintln!("Error: {}", e);
-                        None
-                    }
-                };
-
-                if let Some(quote_rsp) = quote_rsp {
-                    new_status.quote_response_map.insert(format!("{}_{}", input_mint, output_mint), quote_rsp);
-                    if new_status.path_tail < max_path_len {
-                        queue.push_back(new_status);
-                    } else {
-                        eprintln!("Path length exceeds the maximum length");
-                    }
+        eprintln!("level size: {}", level.len());
+
+        // expansions[i] = (index of the status in the level, edge to extend it with), in sequential BFS order
+        let mut expansions: Vec<(usize, usize)> = vec![];
+        for (status_idx, status) in level.iter().enumerate() {
+            // the root status has no edge yet, it sits on the start node
+            let current_node_id = if status.path_tail == 0 { start_node_id } else { graph.to[status.current_edge_id] };
+            for edge_idx in graph.out_edges(current_node_id) {
+                if pruning.live_edges[edge_idx] && !status.visited[graph.to[edge_idx]] {
+                    expansions.push((status_idx, edge_idx));
                 }
             }
-            
-            match graph.next[edge_idx] {
-                Some(idx) => edge_idx = idx,
-                None => break,
+        }
+
+        // Only the (edge, amount) pairs that haven't been quoted in this search are requested, once each
+        let mut join_set = JoinSet::new();
+        let mut requested: HashSet<(usize, u64)> = HashSet::new();
+        for &(status_idx, edge_idx) in &expansions {
+            // Each leg is quoted with the out amount of the previous leg
+            let amount = level[status_idx].current_amount;
+            if quote_cache.contains_key(&(edge_idx, amount)) || !requested.insert((edge_idx, amount)) {
+                cache_hits += 1;
+                continue;
+            }
+            let semaphore = semaphore.clone();
+            let quote_fn = quote_fn.clone();
+            let quote_params = graph.quote_params(edge_idx, amount);
+
+            join_set.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.unwrap();
+                ((edge_idx, amount), quote_leg(edge_idx, quote_params, quote_fn).await)
+            });
+        }
+
+        loop {
+            let join_result = tokio::select! {
+                join_result = join_set.join_next() => match join_result {
+                    Some(join_result) => join_result,
+                    None => break,
+                },
+                stop = limits.stopped() => {
+                    completion = stop;
+                    join_set.abort_all();
+                    break;
+                },
+            };
+            match join_result {
+                Ok((key, leg)) => {
+                    quote_cache.insert(key, leg.map(Arc::new));
+                },
+                Err(e) => eprintln!("Task panicked: {}", e)
             }
         }
+
+        let mut next_level: VecDeque<BFSStatus> = VecDeque::new();
+        for (status_idx, edge_idx) in expansions {
+            let amount = level[status_idx].current_amount;
+            let Some(Some(leg)) = quote_cache.get(&(edge_idx, amount)) else {
+                continue;
+            };
+            let mut new_status = level[status_idx].clone();
+            new_status.current_edge_id = edge_idx;
+            new_status.visited[graph.to[edge_idx]] = true;
+            new_status.path[new_status.path_tail] = edge_idx;
+            new_status.path_tail += 1;
+            new_status.current_amount = leg.out_amount;
+            new_status.legs.push(leg.clone());
+            push_status(new_status, graph, start_node_id, max_path_len, &mut next_level, &mut opportunities);
+        }
+        if completion != SearchCompletion::Finished {
+            break;
+        }
+        levels_completed += 1;
+        level = next_level;
+    }
+    eprintln!("{} quotes requested, {} served from the search cache", quote_cache.len(), cache_hits);
+    Some(SearchOutcome {
+        opportunities,
+        completion,
+        levels_completed,
+    })
+}
+
+// A status whose last edge returns to the start node is a complete cycle, otherwise it is expanded further
+// A cycle of exactly max_path_len legs is kept: the original BFS checked the length before the return and dropped it.
+// Paths that reach max_path_len without returning are dropped
+pub(crate) fn push_status(status: BFSStatus, graph: &StaticGraph, start_node_id: usize, max_path_len: usize,
+                          queue: &mut VecDeque<BFSStatus>, opportunities: &mut Vec<Opportunity>) {
+    if graph.to[status.current_edge_id] == start_node_id {
+        opportunities.push(Opportunity::new(status.legs.iter().map(|leg| leg.as_ref().clone()).collect()));
+    } else if status.path_tail < max_path_len {
+        queue.push_back(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use super::*;
+
+    type QuoteFuture = Pin<Box<dyn Future<Output = Result<QuoteResponse, Box<dyn std::error::Error + Send>>> + Send>>;
+
+    // S <-> A <-> B <-> S
+    fn graph() -> StaticGraph {
+        GraphBuilder::new()
+            .test_nodes(&[("S", Some(6)), ("A", Some(6)), ("B", Some(6))])
+            .mint_pair("S", "A")
+            .mint_pair("A", "B")
+            .mint_pair("B", "S")
+            .build()
+            .unwrap()
     }
-    Some(opportunities)
-}
\ No newline at end of file
+
+    // Every quote returns out_pct % of its input, the n-th request answering after 20 - n ms,
+    // so the first requests of a level are the last to answer. calls counts the requests
+    fn quote_fn(calls: Arc<AtomicUsize>, out_pct: u64) -> impl Fn(QuoteParams) -> QuoteFuture + Clone + Send + 'static {
+        move |params| {
+            let n = calls.fetch_add(1, Ordering::SeqCst) as u64;
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(20u64.saturating_sub(n))).await;
+                Ok(QuoteResponse::for_params(&params, params.amount() * out_pct / 100))
+            })
+        }
+    }
+
+    fn cycles(outcome: &SearchOutcome) -> Vec<(Vec<usize>, u64)> {
+        outcome.opportunities.iter().map(|opportunity| (opportunity.edge_ids(), opportunity.end_amount)).collect()
+    }
+
+    #[tokio::test]
+    async fn the_order_of_the_answers_does_not_change_the_result() {
+        let graph = graph();
+        let start_node = graph.node_id("S").unwrap();
+        let sequential = search_until(&graph, start_node, 1_000_000, 3, 1, SearchLimits::none(),
+                                      quote_fn(Arc::new(AtomicUsize::new(0)), 101)).await.unwrap();
+        let concurrent = search_until(&graph, start_node, 1_000_000, 3, 8, SearchLimits::none(),
+                                      quote_fn(Arc::new(AtomicUsize::new(0)), 101)).await.unwrap();
+        assert_eq!(cycles(&sequential), cycles(&concurrent));
+        assert_eq!(concurrent.completion, SearchCompletion::Finished);
+        assert_eq!(concurrent.levels_completed, 3);
+
+        // level by level: the two 2-cycles, then the two triangles, each leg quoted with the previous out amount
+        let lengths: Vec<usize> = concurrent.opportunities.iter().map(|opportunity| opportunity.legs.len()).collect();
+        assert_eq!(lengths, [2, 2, 3, 3]);
+        for opportunity in &concurrent.opportunities {
+            assert!(opportunity.legs.windows(2).all(|pair| pair[1].in_amount == pair[0].out_amount));
+            assert_eq!(opportunity.symbols(&graph).first(), Some(&"S"));
+            assert_eq!(opportunity.symbols(&graph).last(), Some(&"S"));
+        }
+    }
+
+    #[tokio::test]
+    async fn an_edge_quoted_again_at_the_same_amount_comes_from_the_cache() {
+        let graph = graph();
+        let start_node = graph.node_id("S").unwrap();
+        // at a rate of 1, every path reaches an edge with the start amount
+        let calls = Arc::new(AtomicUsize::new(0));
+        let outcome = search_until(&graph, start_node, 1_000_000, 3, 8, SearchLimits::none(),
+                                   quote_fn(calls.clone(), 100)).await.unwrap();
+        // S -> A -> B -> S and S -> B -> A -> S reuse the B -> S and A -> S quotes of the 2-cycles:
+        // each of the 6 edges is quoted once
+        assert_eq!(calls.load(Ordering::SeqCst), 6);
+        assert_eq!(outcome.opportunities.len(), 4);
+        assert!(outcome.opportunities.iter().all(|opportunity| opportunity.gross_profit == 0));
+
+        // at another rate the amounts differ, and the closing legs of the triangles are quoted again
+        let calls = Arc::new(AtomicUsize::new(0));
+        search_until(&graph, start_node, 1_000_000, 3, 8, SearchLimits::none(), quote_fn(calls.clone(), 101)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 8);
+    }
+
+    // Like quote_fn, without delay, but the quotes out of B never answer
+    fn stuck_on_b(params: QuoteParams) -> QuoteFuture {
+        Box::pin(async move {
+            let response = QuoteResponse::for_params(&params, params.amount() * 101 / 100);
+            if response.input_mint == "B" {
+                std::future::pending::<()>().await;
+            }
+            Ok(response)
+        })
+    }
+
+    #[tokio::test]
+    async fn the_deadline_keeps_what_was_found_before_it() {
+        let graph = graph();
+        let start_node = graph.node_id("S").unwrap();
+        let limits = SearchLimits::none().with_deadline(Instant::now() + Duration::from_millis(50));
+        let outcome = search_until(&graph, start_node, 1_000_000, 3, 8, limits, stuck_on_b).await.unwrap();
+        assert_eq!(outcome.completion, SearchCompletion::DeadlineReached);
+        // the first level is complete, in the second one only the quotes out of A answered
+        assert_eq!(outcome.levels_completed, 1);
+        assert_eq!(outcome.opportunities.len(), 1);
+        assert_eq!(outcome.opportunities[0].symbols(&graph), ["S", "A", "S"]);
+
+        let limits = SearchLimits::none().with_deadline(Instant::now());
+        let outcome = search_until(&graph, start_node, 1_000_000, 3, 8, limits, stuck_on_b).await.unwrap();
+        assert_eq!(outcome.completion, SearchCompletion::DeadlineReached);
+        assert_eq!(outcome.levels_completed, 0);
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_search_stops_waiting_for_its_quotes() {
+        let graph = graph();
+        let start_node = graph.node_id("S").unwrap();
+        let (cancel, cancelled) = watch::channel(false);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            cancel.send(true).unwrap();
+        });
+        let limits = SearchLimits::none().with_cancel(cancelled);
+        let outcome = search_until(&graph, start_node, 1_000_000, 3, 8, limits, stuck_on_b).await.unwrap();
+        assert_eq!(outcome.completion, SearchCompletion::Cancelled);
+        assert_eq!(outcome.levels_completed, 1);
+        assert_eq!(outcome.opportunities.len(), 1);
+    }
+}
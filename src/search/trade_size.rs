@@ -0,0 +1,243 @@
+// Find the input amount that maximizes the absolute profit of a cycle
+// The profit of a cycle is roughly concave in the input amount: small trades don't cover the price gap,
+// large trades eat the liquidity. So the amount is searched with a golden-section search over a bounded range,
+// each evaluation re-quoting every leg with the out amount of the previous leg,
+// or estimating it from the quote ladders of a snapshot when no network call should be made.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+use serde::Serialize;
+use crate::graph::static_graph::StaticGraph;
+use crate::jupiter::quote::{QuoteParams, QuoteResponse};
+use crate::search::cached_search::{estimate_cycle, usable_legs, QuoteSnapshot};
+use crate::search::opportunity::{Leg, Opportunity};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ProfitSample {
+    pub amount: u64,
+    pub gross_profit: i128,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TradeSizeResult {
+    pub best: Option<Opportunity>, // the evaluated amount with the highest profit, None if every evaluation failed
+    pub samples: Vec<ProfitSample>, // every successfully evaluated amount, sorted by amount
+}
+
+// Quote the cycle (a list of edge ids) with `amount` as the input of the first leg
+pub async fn evaluate_cycle<F, Fut>(graph: &StaticGraph, cycle: &[usize], amount: u64, quote_fn: &F) -> Option<Opportunity>
+where
+    F: Fn(QuoteParams) -> Fut,
+    Fut: Future<Output = Result<QuoteResponse, Box<dyn std::error::Error + Send>>>,
+{
+    let mut legs: Vec<Leg> = Vec::with_capacity(cycle.len());
+    let mut current_amount = amount;
+    for &edge_idx in cycle {
+        let quote_rsp = match quote_fn(graph.quote_params(edge_idx, current_amount)).await {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Error quoting edge {} with amount {}: {}", edge_idx, current_amount, e);
+                return None;
+            }
+        };
+        let leg = Leg::from_quote(edge_idx, &quote_rsp, SystemTime::now())?;
+        current_amount = leg.out_amount;
+        legs.push(leg);
+    }
+    Some(Opportunity::new(legs))
+}
+
+// Golden-section search for the most profitable input amount in [min_amount, max_amount]
+// Both bounds are evaluated too, and the best of all evaluated amounts is returned,
+// so a curve that isn't unimodal still gives the best point that was sampled
+pub async fn optimize_trade_size<F, Fut>(graph: &StaticGraph, cycle: &[usize], min_amount: u64, max_amount: u64,
+                                         iterations: usize, quote_fn: F) -> TradeSizeResult
+where
+    F: Fn(QuoteParams) -> Fut,
+    Fut: Future<Output = Result<QuoteResponse, Box<dyn std::error::Error + Send>>>,
+{
+    let mut search = GoldenSection::new(min_amount, max_amount, iterations);
+    while let Some(amount) = search.next_amount() {
+        let opportunity = evaluate_cycle(graph, cycle, amount, &quote_fn).await;
+        search.record(amount, opportunity);
+    }
+    search.finish()
+}
+
+// Same search over the cached quotes of a snapshot, each amount estimated from the quote ladders of the edges
+// (or with the cached rates for the edges without a ladder), so it costs no quote
+pub fn optimize_trade_size_estimated(snapshot: &QuoteSnapshot, cycle: &[usize], min_amount: u64, max_amount: u64,
+                                     iterations: usize, max_quote_age: Duration) -> TradeSizeResult {
+    let (usable, _) = usable_legs(snapshot, max_quote_age);
+    let mut search = GoldenSection::new(min_amount, max_amount, iterations);
+    while let Some(amount) = search.next_amount() {
+        search.record(amount, estimate_cycle(&usable, cycle, amount));
+    }
+    search.finish()
+}
+
+const INV_PHI: f64 = 0.618_033_988_749_895; // (sqrt(5) - 1) / 2
+
+// The state of a golden-section search, the caller evaluates the amounts it asks for
+// An amount is evaluated at most once, a failed evaluation counts as the lowest possible profit
+struct GoldenSection {
+    evaluated: BTreeMap<u64, Option<Opportunity>>,
+    bounds: [u64; 2],
+    lo: f64,
+    hi: f64,
+    x1: f64,
+    x2: f64,
+    f1: Option<i128>, // the profit at x1, None until evaluated
+    f2: Option<i128>,
+    iterations: usize, // the iterations left
+}
+
+impl GoldenSection {
+    fn new(min_amount: u64, max_amount: u64, iterations: usize) -> Self {
+        let lo = min_amount as f64;
+        let hi = max_amount.max(min_amount) as f64;
+        Self {
+            evaluated: BTreeMap::new(),
+            bounds: [min_amount, hi as u64],
+            lo,
+            hi,
+            x1: hi - INV_PHI * (hi - lo),
+            x2: lo + INV_PHI * (hi - lo),
+            f1: None,
+            f2: None,
+            iterations,
+        }
+    }
+
+    fn profit(&self, amount: u64) -> Option<i128> {
+        self.evaluated.get(&amount).map(|opportunity| opportunity.as_ref().map_or(i128::MIN, |o| o.gross_profit))
+    }
+
+    // The next amount to evaluate, None once the search is over
+    fn next_amount(&mut self) -> Option<u64> {
+        if let Some(&bound) = self.bounds.iter().find(|bound| !self.evaluated.contains_key(bound)) {
+            return Some(bound);
+        }
+        loop {
+            match (self.f1, self.f2) {
+                (None, _) => match self.profit(self.x1 as u64) {
+                    Some(profit) => self.f1 = Some(profit),
+                    None => return Some(self.x1 as u64),
+                },
+                (_, None) => match self.profit(self.x2 as u64) {
+                    Some(profit) => self.f2 = Some(profit),
+                    None => return Some(self.x2 as u64),
+                },
+                (Some(f1), Some(f2)) => {
+                    if self.iterations == 0 || self.hi - self.lo < 1.0 {
+                        return None;
+                    }
+                    self.iterations -= 1;
+                    if f1 < f2 {
+                        self.lo = self.x1;
+                        self.x1 = self.x2;
+                        self.f1 = self.f2;
+                        self.x2 = self.lo + INV_PHI * (self.hi - self.lo);
+                        self.f2 = None;
+                    } else {
+                        self.hi = self.x2;
+                        self.x2 = self.x1;
+                        self.f2 = self.f1;
+                        self.x1 = self.hi - INV_PHI * (self.hi - self.lo);
+                        self.f1 = None;
+                    }
+                },
+            }
+        }
+    }
+
+    fn record(&mut self, amount: u64, opportunity: Option<Opportunity>) {
+        self.evaluated.insert(amount, opportunity);
+    }
+
+    fn finish(self) -> TradeSizeResult {
+        let samples = self.evaluated.iter()
+            .filter_map(|(&amount, opportunity)| opportunity.as_ref().map(|o| ProfitSample { amount, gross_profit: o.gross_profit }))
+            .collect();
+        let best = self.evaluated.into_values()
+            .flatten()
+            .max_by_key(|o| o.gross_profit);
+        TradeSizeResult {
+            best,
+            samples,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::builder::GraphBuilder;
+    use crate::graph::static_graph::DexFilter;
+
+    // S <-> A, quoted by `quote`: S -> A pays x + x * (20000 - x) / 1000 for x, the most profit at x = 10000,
+    // and A -> S gives back its input
+    fn graph() -> (StaticGraph, Vec<usize>) {
+        let graph = GraphBuilder::new()
+            .test_nodes(&[("S", Some(6)), ("A", Some(6))])
+            .mint_pair("S", "A")
+            .build()
+            .unwrap();
+        let cycle = vec![graph.find_edge("S", "A", &DexFilter::default()).unwrap(),
+                         graph.find_edge("A", "S", &DexFilter::default()).unwrap()];
+        (graph, cycle)
+    }
+
+    async fn quote(params: QuoteParams) -> Result<QuoteResponse, Box<dyn std::error::Error + Send>> {
+        let amount = params.amount() as i64;
+        let mut response = QuoteResponse::for_params(&params, amount as u64);
+        if response.input_mint == "S" {
+            response.out_amount = (amount + amount * (20_000 - amount) / 1_000).max(0).to_string();
+        }
+        Ok(response)
+    }
+
+    #[tokio::test]
+    async fn finds_the_most_profitable_amount() {
+        let (graph, cycle) = graph();
+        let result = optimize_trade_size(&graph, &cycle, 1_000, 50_000, 30, quote).await;
+        let best = result.best.unwrap();
+        assert!((9_900..=10_100).contains(&best.start_amount), "best amount {}", best.start_amount);
+        assert!(best.gross_profit > 99_900);
+        // both bounds are sampled, the samples are sorted and each amount is quoted once
+        assert_eq!(result.samples.first().map(|sample| sample.amount), Some(1_000));
+        assert_eq!(result.samples.last().map(|sample| sample.amount), Some(50_000));
+        assert!(result.samples.windows(2).all(|pair| pair[0].amount < pair[1].amount));
+        assert!(result.samples.len() <= 30 + 4);
+    }
+
+    #[tokio::test]
+    async fn a_failed_quote_is_the_lowest_profit() {
+        let (graph, cycle) = graph();
+        // no route for more than 15000 S
+        let failing_above = |params: QuoteParams| async move {
+            let amount = params.amount();
+            let response = quote(params).await?;
+            if response.input_mint == "S" && amount > 15_000 {
+                return Err(Box::new(std::io::Error::other("no route")) as Box<dyn std::error::Error + Send>);
+            }
+            Ok(response)
+        };
+        let result = optimize_trade_size(&graph, &cycle, 1_000, 50_000, 30, failing_above).await;
+        assert!((9_900..=10_100).contains(&result.best.unwrap().start_amount));
+        assert!(result.samples.iter().all(|sample| sample.amount <= 15_000));
+
+        let never = |_: QuoteParams| async { Err(Box::new(std::io::Error::other("no route")) as Box<dyn std::error::Error + Send>) };
+        let result = optimize_trade_size(&graph, &cycle, 1_000, 50_000, 30, never).await;
+        assert!(result.best.is_none());
+        assert!(result.samples.is_empty());
+    }
+
+    #[tokio::test]
+    async fn an_empty_range_evaluates_its_only_amount() {
+        let (graph, cycle) = graph();
+        let result = optimize_trade_size(&graph, &cycle, 5_000, 1_000, 30, quote).await;
+        assert_eq!(result.samples.iter().map(|sample| sample.amount).collect::<Vec<_>>(), [5_000]);
+    }
+}